@@ -0,0 +1,105 @@
+use rayon::prelude::*;
+use std::{
+    fs::{self, File},
+    io,
+    path::Path,
+};
+use thiserror::Error;
+use zip::{result::ZipError, ZipArchive};
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("Failed to extract the archive: {0}")]
+    Io(#[from] io::Error),
+    #[error("Invalid zip file: {0}")]
+    Zip(#[from] ZipError),
+    #[error("The entry {0:?} would be extracted outside of the project")]
+    UnsafePath(String),
+}
+
+/// Extract the zip archive into the existing directory `dest`, in parallel.
+///
+/// Entries whose paths are absolute or escape `dest` with `..` are rejected.
+pub fn extract_zip(archive: &Path, dest: &Path) -> Result<(), ArchiveError> {
+    let len = ZipArchive::new(File::open(archive)?)?.len();
+
+    (0..len).into_par_iter().try_for_each_init(
+        // The reader can't be shared, so each thread opens the archive itself
+        || None,
+        |zip, index| {
+            if zip.is_none() {
+                *zip = Some(ZipArchive::new(File::open(archive)?)?);
+            }
+            let mut file = zip.as_mut().unwrap().by_index(index)?;
+            let path = file
+                .enclosed_name()
+                .ok_or_else(|| ArchiveError::UnsafePath(file.name().to_string()))?
+                .to_path_buf();
+            let path = dest.join(path);
+
+            // Other threads can create the same directories concurrently,
+            // which `create_dir_all` tolerates
+            if file.is_dir() {
+                fs::create_dir_all(&path)?;
+            } else {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                io::copy(&mut file, &mut File::create(&path)?)?;
+            }
+
+            Ok(())
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::{write::FileOptions, ZipWriter};
+
+    fn create_zip(dir: &Path, names: &[&str]) -> std::path::PathBuf {
+        let path = dir.join("project.zip");
+        let mut writer = ZipWriter::new(File::create(&path).unwrap());
+        for name in names {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(name.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn test_extract_zip() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = create_zip(
+            dir.path(),
+            &["main.tex", "chapters/intro.tex", "chapters/outro.tex"],
+        );
+        let dest = dir.path().join("project");
+        fs::create_dir(&dest).unwrap();
+
+        extract_zip(&archive, &dest).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dest.join("chapters/intro.tex")).unwrap(),
+            "chapters/intro.tex"
+        );
+        assert!(dest.join("main.tex").is_file());
+    }
+
+    #[test]
+    fn test_extract_zip_slip() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = create_zip(dir.path(), &["../evil.tex"]);
+        let dest = dir.path().join("project");
+        fs::create_dir(&dest).unwrap();
+
+        assert!(matches!(
+            extract_zip(&archive, &dest),
+            Err(ArchiveError::UnsafePath(name)) if name == "../evil.tex"
+        ));
+        assert!(!dir.path().join("evil.tex").exists());
+    }
+}
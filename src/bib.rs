@@ -0,0 +1,236 @@
+use crate::mapping::Mapping;
+use regex::Regex;
+use std::{borrow::Cow, ops::Range, path::Path, sync::LazyLock};
+
+static FILE_FIELD_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)(?:^|[,\s])file\s*=\s*([{"])"#).unwrap());
+
+/// Determine if the file is a BibTeX database, whose `file` fields can reference attachments.
+pub fn is_bib(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("bib"))
+}
+
+/// The paths of the attachments in the `file` fields of a `.bib` file, unescaped.
+///
+/// A field is either a plain path, like `file = {figures/scan.pdf}`,
+/// or a `;`-separated list of `description:path:type` triples, like JabRef writes them.
+pub fn file_paths(content: &str) -> Vec<String> {
+    field_values(content)
+        .flat_map(|value| path_ranges(&content[value]))
+        .map(|(path, _)| path)
+        .collect()
+}
+
+/// Rewrite the paths in the `file` fields which resolve to project files to their flattened names.
+///
+/// Other paths, like absolute paths to a local library, are left unchanged.
+pub fn rewrite_file_fields<'a>(content: &'a str, mapping: &Mapping) -> Cow<'a, str> {
+    let mut rewritten = String::new();
+    let mut last = 0;
+
+    for value in field_values(content) {
+        for (path, range) in path_ranges(&content[value.clone()]) {
+            let Some(name) = mapping
+                .resolve(&path, &[])
+                .and_then(|source| mapping.name(source))
+            else {
+                continue;
+            };
+
+            let start = value.start + range.start;
+            rewritten.push_str(&content[last..start]);
+            rewritten.push_str(&escape(name));
+            last = value.start + range.end;
+        }
+    }
+
+    if last == 0 {
+        return Cow::Borrowed(content);
+    }
+    rewritten.push_str(&content[last..]);
+    Cow::Owned(rewritten)
+}
+
+/// The ranges of the values of the `file` fields, without their delimiters.
+///
+/// Only fields of an entry count, not text like `file = {...}` in the value of another field.
+fn field_values(content: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    // The brace depth at the position, which is 1 for the fields of an entry
+    let mut position = 0;
+    let mut depth = 0;
+
+    FILE_FIELD_REGEX
+        .captures_iter(content)
+        .filter_map(move |capture| {
+            let delimiter = capture.get(1).unwrap();
+            depth += brace_balance(&content[position..delimiter.start()]);
+            position = delimiter.start();
+            if depth != 1 {
+                return None;
+            }
+
+            let start = delimiter.end();
+            let mut depth = 1;
+            let mut escaped = false;
+
+            for (index, char) in content[start..].char_indices() {
+                match char {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '{' => depth += 1,
+                    '}' if delimiter.as_str() == "{" && depth == 1 => {
+                        return Some(start..start + index);
+                    }
+                    '}' => depth -= 1,
+                    '"' if delimiter.as_str() == "\"" && depth == 1 => {
+                        return Some(start..start + index);
+                    }
+                    _ => {}
+                }
+            }
+
+            None
+        })
+}
+
+/// The number of opening minus closing braces of the text, ignoring escaped ones.
+fn brace_balance(text: &str) -> i32 {
+    let mut balance = 0;
+    let mut escaped = false;
+
+    for char in text.chars() {
+        match char {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => balance += 1,
+            '}' => balance -= 1,
+            _ => {}
+        }
+    }
+
+    balance
+}
+
+/// The unescaped paths of a `file` field value, with their ranges in the value.
+fn path_ranges(value: &str) -> Vec<(String, Range<usize>)> {
+    split_unescaped(value, ';', 0..value.len())
+        .into_iter()
+        .filter_map(|entry| {
+            let parts = split_unescaped(value, ':', entry.clone());
+            let range = match &parts[..] {
+                [path] => path.clone(),
+                [_, path, _] => path.clone(),
+                _ => return None,
+            };
+
+            let trimmed = value[range.clone()].trim();
+            let start = range.start + value[range.clone()].find(trimmed).unwrap_or(0);
+            let range = start..start + trimmed.len();
+            (!trimmed.is_empty()).then(|| (unescape(&value[range.clone()]), range))
+        })
+        .collect()
+}
+
+/// Split the range of the text at the separators which are not escaped by a backslash.
+fn split_unescaped(text: &str, separator: char, range: Range<usize>) -> Vec<Range<usize>> {
+    let mut parts = Vec::new();
+    let mut start = range.start;
+    let mut escaped = false;
+
+    for (index, char) in text[range.clone()].char_indices() {
+        let index = range.start + index;
+        match char {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            _ if char == separator => {
+                parts.push(start..index);
+                start = index + char.len_utf8();
+            }
+            _ => {}
+        }
+    }
+
+    parts.push(start..range.end);
+    parts
+}
+
+/// Remove the backslashes escaping `:`, `;` and `\` in a JabRef file field.
+fn unescape(path: &str) -> String {
+    let mut unescaped = String::with_capacity(path.len());
+    let mut chars = path.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        match (char, chars.peek()) {
+            ('\\', Some(':' | ';' | '\\')) => unescaped.push(chars.next().unwrap()),
+            _ => unescaped.push(char),
+        }
+    }
+
+    unescaped
+}
+
+/// Escape `:`, `;` and `\` for a JabRef file field.
+fn escape(path: &str) -> Cow<'_, str> {
+    if !path.contains([':', ';', '\\']) {
+        return Cow::Borrowed(path);
+    }
+
+    let mut escaped = String::with_capacity(path.len());
+    for char in path.chars() {
+        if matches!(char, ':' | ';' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(char);
+    }
+    Cow::Owned(escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &str = r#"@article{scan,
+    title = {Profile: a file = {not/a/field}},
+    file = {figures/scan.pdf},
+}
+@article{jabref,
+    file = ":papers/a.pdf:PDF;Slides\: talk:papers/talk.pdf:PDF;:C\:\\Users\\me\\b.pdf:PDF",
+}"#;
+
+    #[test]
+    fn test_file_paths() {
+        assert_eq!(
+            file_paths(CONTENT),
+            vec![
+                "figures/scan.pdf",
+                "papers/a.pdf",
+                "papers/talk.pdf",
+                r"C:\Users\me\b.pdf",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rewrite_file_fields() {
+        let mut mapping = Mapping::default();
+        for source in ["figures/scan.pdf", "papers/a.pdf", "papers/talk.pdf"] {
+            mapping.insert(source.to_string(), source.replace('/', "__"));
+        }
+
+        assert_eq!(
+            rewrite_file_fields(CONTENT, &mapping),
+            r#"@article{scan,
+    title = {Profile: a file = {not/a/field}},
+    file = {figures__scan.pdf},
+}
+@article{jabref,
+    file = ":papers__a.pdf:PDF;Slides\: talk:papers__talk.pdf:PDF;:C\:\\Users\\me\\b.pdf:PDF",
+}"#
+        );
+        assert!(matches!(
+            rewrite_file_fields("@misc{x, file = {missing.pdf}}", &mapping),
+            Cow::Borrowed(_)
+        ));
+    }
+}
@@ -0,0 +1,278 @@
+use regex::Regex;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// The extensions tried when resolving references of input-like commands.
+pub const TEX_EXTENSIONS: [&str; 1] = ["tex"];
+
+/// The extensions tried when resolving references of graphics-like commands.
+pub const GRAPHICS_EXTENSIONS: [&str; 7] = ["pdf", "png", "jpg", "jpeg", "eps", "ps", "svg"];
+
+/// The extensions tried when resolving references of bibliography-like commands.
+pub const BIBLIOGRAPHY_EXTENSIONS: [&str; 1] = ["bib"];
+
+/// The extensions tried when resolving references of bibliography style commands.
+pub const BIBLIOGRAPHY_STYLE_EXTENSIONS: [&str; 1] = ["bst"];
+
+/// The extensions tried when resolving references of package-loading commands.
+pub const PACKAGE_EXTENSIONS: [&str; 1] = ["sty"];
+
+/// The extensions tried when resolving references of class-loading commands.
+pub const CLASS_EXTENSIONS: [&str; 1] = ["cls"];
+
+#[derive(Debug, Error)]
+pub enum CommandNameError {
+    #[error("Invalid command name {0:?}, expected letters only")]
+    Invalid(String),
+}
+
+/// How the path argument of an include command is interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandKind {
+    /// Like `\input`, referencing a `.tex` file.
+    Input,
+    /// Like `\includegraphics`, referencing an image.
+    Graphics,
+    /// Like `\bibliography`, referencing a comma-separated list of `.bib` files.
+    Bibliography,
+    /// Like `\bibliographystyle`, referencing a `.bst` file,
+    /// of which only the local ones are rewritten.
+    BibliographyStyle,
+    /// Like `\includestandalone`, referencing a `.tex` file with an optional precompiled `.pdf`.
+    Standalone,
+    /// Like `\usepackage`, referencing a comma-separated list of packages,
+    /// of which only the local `.sty` files are rewritten.
+    Package,
+    /// Like `\LoadClass`, referencing a document class,
+    /// of which only the local `.cls` files are rewritten.
+    Class,
+}
+
+impl CommandKind {
+    /// The name of the kind, as used in the `[commands]` config section.
+    pub fn name(self) -> &'static str {
+        match self {
+            CommandKind::Input => "input",
+            CommandKind::Graphics => "graphics",
+            CommandKind::Bibliography => "bibliography",
+            CommandKind::BibliographyStyle => "bibliography-style",
+            CommandKind::Standalone => "standalone",
+            CommandKind::Package => "package",
+            CommandKind::Class => "class",
+        }
+    }
+
+    /// The extensions LaTeX tries when the reference has none.
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            CommandKind::Input | CommandKind::Standalone => &TEX_EXTENSIONS,
+            CommandKind::Graphics => &GRAPHICS_EXTENSIONS,
+            CommandKind::Bibliography => &BIBLIOGRAPHY_EXTENSIONS,
+            CommandKind::BibliographyStyle => &BIBLIOGRAPHY_STYLE_EXTENSIONS,
+            CommandKind::Package => &PACKAGE_EXTENSIONS,
+            CommandKind::Class => &CLASS_EXTENSIONS,
+        }
+    }
+}
+
+/// The include commands whose path arguments are rewritten.
+#[derive(Debug, Clone)]
+pub struct Commands {
+    kinds: BTreeMap<String, CommandKind>,
+    /// The position of the braced path argument of the commands where it is not the first.
+    path_args: BTreeMap<String, usize>,
+    /// The braced form of the commands by position of the path argument,
+    /// or the plain TeX form `\input path` or `\@@input path`,
+    /// where the path is terminated by whitespace.
+    /// Braced paths can contain one level of groups, like `\detokenize{...}`.
+    import_regexes: Vec<Regex>,
+    /// The start of a braced command, up to the opening brace of the path.
+    import_start_regex: Regex,
+    /// A command at the end of the text which still awaits its arguments.
+    import_pending_regex: Regex,
+}
+
+/// The space between the parts of a command, which can span lines and contain comments,
+/// like `\includegraphics % figure` with the options on the next line.
+const GAP: &str = r"(?:[ \t]|%[^\n]*\n|\r?\n)*";
+
+impl Default for Commands {
+    fn default() -> Self {
+        Self::with_custom([])
+    }
+}
+
+impl Commands {
+    /// The built-in commands, extended by the given custom commands.
+    pub fn with_custom(custom: impl IntoIterator<Item = (String, CommandKind)>) -> Self {
+        Self::with_custom_path_args(custom.into_iter().map(|(name, kind)| (name, kind, 1)))
+    }
+
+    /// The built-in commands, extended by the given custom commands
+    /// with the 1-based position of their path argument, e.g. 2 for `\myinclude{caption}{path}`.
+    pub fn with_custom_path_args(
+        custom: impl IntoIterator<Item = (String, CommandKind, usize)>,
+    ) -> Self {
+        let mut kinds = BTreeMap::from([
+            ("input".to_string(), CommandKind::Input),
+            // The primitive, which LaTeX saves before redefining `\input`
+            ("@@input".to_string(), CommandKind::Input),
+            ("include".to_string(), CommandKind::Input),
+            ("loadglsentries".to_string(), CommandKind::Input),
+            ("includegraphics".to_string(), CommandKind::Graphics),
+            ("includestandalone".to_string(), CommandKind::Standalone),
+            ("usepackage".to_string(), CommandKind::Package),
+            ("RequirePackage".to_string(), CommandKind::Package),
+            ("documentclass".to_string(), CommandKind::Class),
+            ("LoadClass".to_string(), CommandKind::Class),
+            ("LoadClassWithOptions".to_string(), CommandKind::Class),
+            ("bibliography".to_string(), CommandKind::Bibliography),
+            ("addbibresource".to_string(), CommandKind::Bibliography),
+            (
+                "bibliographystyle".to_string(),
+                CommandKind::BibliographyStyle,
+            ),
+        ]);
+        let mut path_args = BTreeMap::new();
+        for (name, kind, path_arg) in custom {
+            path_args.remove(&name);
+            if path_arg > 1 {
+                path_args.insert(name.clone(), path_arg);
+            }
+            kinds.insert(name, kind);
+        }
+
+        let names: Vec<_> = kinds.keys().map(|name| name_pattern(name)).collect();
+        let start = format!(r"\\({}){GAP}(\[[^]]*\])?{GAP}\{{", names.join("|"));
+        let pending = format!(
+            r"\\(?:{})(?:[ \t]|%[^\n]*|\r?\n)*(?:\[[^]]*(?:\]{GAP}(?:%[^\n]*)?)?)?$",
+            names.join("|")
+        );
+
+        // The commands by position of the path argument, where the preceding ones are skipped
+        let mut by_path_arg: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+        for name in kinds.keys() {
+            let path_arg = path_args.get(name).copied().unwrap_or(1);
+            by_path_arg
+                .entry(path_arg)
+                .or_default()
+                .push(name_pattern(name));
+        }
+        let import_regexes = by_path_arg
+            .into_iter()
+            .map(|(path_arg, names)| {
+                let braced = format!(
+                    r"\\({}){GAP}(\[[^]]*\])?{GAP}(?:\{{[^{{}}]*\}}{GAP}){{{}}}\{{((?:[^{{}}]|\{{[^{{}}]*\}})*)\}}",
+                    names.join("|"),
+                    path_arg - 1
+                );
+                let pattern = if path_arg == 1 {
+                    format!(r"{braced}|\\(?:@@)?input[ \t]+([^\s{{}}%\\]+)")
+                } else {
+                    braced
+                };
+                Regex::new(&pattern).unwrap()
+            })
+            .collect();
+
+        Self {
+            kinds,
+            path_args,
+            import_regexes,
+            import_start_regex: Regex::new(&start).unwrap(),
+            import_pending_regex: Regex::new(&pending).unwrap(),
+        }
+    }
+
+    /// The kind of the command with the given name, without the backslash.
+    pub fn kind(&self, name: &str) -> Option<CommandKind> {
+        self.kinds.get(name).copied().or_else(|| {
+            name.starts_with("bibliography")
+                .then_some(CommandKind::Bibliography)
+        })
+    }
+
+    /// All commands with their kind, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, CommandKind)> {
+        self.kinds.iter().map(|(name, kind)| (name.as_str(), *kind))
+    }
+
+    /// The 1-based position of the braced path argument of the command.
+    pub fn path_arg(&self, name: &str) -> usize {
+        self.path_args.get(name).copied().unwrap_or(1)
+    }
+
+    pub fn import_regexes(&self) -> &[Regex] {
+        &self.import_regexes
+    }
+
+    pub fn import_start_regex(&self) -> &Regex {
+        &self.import_start_regex
+    }
+
+    pub fn import_pending_regex(&self) -> &Regex {
+        &self.import_pending_regex
+    }
+}
+
+/// The regex matching the command name.
+///
+/// Also covers the variants of the multibib package, like `\bibliographyS`.
+fn name_pattern(name: &str) -> String {
+    match name {
+        "bibliography" => r"bibliography\w*".to_string(),
+        name => regex::escape(name),
+    }
+}
+
+/// Determine if the name is a valid LaTeX control word, which can be used as a command.
+pub fn is_command_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|char| char.is_ascii_alphabetic() || char == '@')
+}
+
+/// Parse a command name given on the command line, where the leading backslash is optional.
+pub fn parse_command_name(name: &str) -> Result<String, CommandNameError> {
+    let name = name.trim_start_matches('\\');
+    if is_command_name(name) {
+        Ok(name.to_string())
+    } else {
+        Err(CommandNameError::Invalid(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kind() {
+        let commands = Commands::with_custom([("deptbib".to_string(), CommandKind::Bibliography)]);
+
+        assert_eq!(commands.kind("include"), Some(CommandKind::Input));
+        assert_eq!(
+            commands.kind("bibliographyS"),
+            Some(CommandKind::Bibliography)
+        );
+        assert_eq!(commands.kind("deptbib"), Some(CommandKind::Bibliography));
+        assert_eq!(
+            commands.kind("bibliographystyle"),
+            Some(CommandKind::BibliographyStyle)
+        );
+        assert_eq!(commands.kind("textbf"), None);
+    }
+
+    #[test]
+    fn test_iter() {
+        let commands = Commands::with_custom([("includesvg".to_string(), CommandKind::Graphics)]);
+        let graphics: Vec<_> = commands
+            .iter()
+            .filter(|(_, kind)| *kind == CommandKind::Graphics)
+            .map(|(name, _)| name)
+            .collect();
+
+        assert_eq!(graphics, vec!["includegraphics", "includesvg"]);
+    }
+}
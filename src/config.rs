@@ -0,0 +1,325 @@
+use crate::commands::{is_command_name, CommandKind};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use regex::Regex;
+use serde::Deserialize;
+use std::{borrow::Cow, fs, io, path::Path};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read the config file: {0}")]
+    Io(#[from] io::Error),
+    #[error("Invalid config file: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Invalid command name {0:?}, expected letters only")]
+    InvalidCommand(String),
+    #[error("Invalid path argument of {0:?}, expected a position starting at 1")]
+    InvalidPathArg(String),
+    #[error("Invalid transform pattern {0:?}: {1}")]
+    InvalidTransform(String, regex::Error),
+    #[error("Invalid root documentation pattern {0:?}: {1}")]
+    InvalidRootDoc(String, globset::Error),
+}
+
+/// The configuration file, in TOML format.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub commands: CommandsConfig,
+    pub files: FilesConfig,
+    pub packages: PackagesConfig,
+    pub transforms: Vec<Transform>,
+}
+
+/// Custom include commands, by the built-in command they behave like.
+///
+/// Commands whose path is not the first braced argument give its position,
+/// like `\myinclude{caption}{path}`:
+///
+/// ```toml
+/// [commands]
+/// input = ["subinput"]
+/// graphics = ["includesvg", { name = "myinclude", path_arg = 2 }]
+/// bibliography = ["deptbib"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommandsConfig {
+    /// Commands like `\input`, referencing `.tex` files.
+    pub input: Vec<CommandEntry>,
+    /// Commands like `\includegraphics`, referencing images.
+    pub graphics: Vec<CommandEntry>,
+    /// Commands like `\bibliography`, referencing comma-separated `.bib` files.
+    pub bibliography: Vec<CommandEntry>,
+}
+
+/// A custom command, given by its name or with the position of its path argument.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum CommandEntry {
+    Name(String),
+    Table(CommandTable),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommandTable {
+    pub name: String,
+    /// The 1-based position of the braced path argument.
+    #[serde(default = "first_arg")]
+    pub path_arg: usize,
+}
+
+fn first_arg() -> usize {
+    1
+}
+
+/// Which files are processed.
+///
+/// ```toml
+/// [files]
+/// processed = ["tex", "cls", "sty", "Rnw", "dtx", "ltx", "md"]
+/// root_docs = ["README*", "LICENSE*", "CITATION.cff", "AUTHORS"]
+/// main = ["texw"]
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilesConfig {
+    /// The extensions of the files whose include commands are rewritten, compared ignoring case.
+    /// In Markdown files, like for Pandoc, fenced code blocks are left unchanged.
+    pub processed: Vec<String>,
+    /// The globs of the documentation files in the project root,
+    /// which are copied with `--include-root-docs`. Compared ignoring case.
+    pub root_docs: Vec<String>,
+    /// The extensions of the files which are only processed if they are main documents,
+    /// i.e. they declare a `\documentclass`, compared ignoring case.
+    pub main: Vec<String>,
+}
+
+impl Default for FilesConfig {
+    fn default() -> Self {
+        Self {
+            processed: ["tex", "cls", "sty", "Rnw", "dtx"]
+                .map(str::to_string)
+                .to_vec(),
+            root_docs: ["README*", "LICENSE*", "CITATION.cff"]
+                .map(str::to_string)
+                .to_vec(),
+            main: Vec::new(),
+        }
+    }
+}
+
+impl FilesConfig {
+    /// Determine if the include commands of the file at the given path are rewritten.
+    pub fn is_processed(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                self.processed
+                    .iter()
+                    .any(|processed| ext.eq_ignore_ascii_case(processed))
+            })
+    }
+
+    /// Determine if the file at the given path is processed if it is a main document.
+    pub fn is_main_extension(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| self.main.iter().any(|main| ext.eq_ignore_ascii_case(main)))
+    }
+
+    /// The set of the `root_docs` globs.
+    pub fn root_docs(&self) -> Result<GlobSet, ConfigError> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &self.root_docs {
+            let glob = GlobBuilder::new(pattern)
+                .case_insensitive(true)
+                .literal_separator(true)
+                .build()
+                .map_err(|err| ConfigError::InvalidRootDoc(pattern.clone(), err))?;
+            builder.add(glob);
+        }
+
+        builder
+            .build()
+            .map_err(|err| ConfigError::InvalidRootDoc(self.root_docs.join(", "), err))
+    }
+}
+
+/// How packages loaded with `\usepackage` are treated.
+///
+/// ```toml
+/// [packages]
+/// system = ["acmart-extras"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PackagesConfig {
+    /// Additional distribution packages, which are never treated as project files.
+    pub system: Vec<String>,
+}
+
+/// A find and replace of the content of the processed files,
+/// applied after the include commands are rewritten.
+///
+/// The transforms are applied in the order they are given.
+/// The replacement can refer to the groups of the pattern, like `$1`:
+///
+/// ```toml
+/// [[transforms]]
+/// pattern = '\\bf\{([^}]*)\}'
+/// replacement = '\textbf{$1}'
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "TransformTable")]
+pub struct Transform {
+    regex: Regex,
+    replacement: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransformTable {
+    pattern: String,
+    replacement: String,
+}
+
+impl TryFrom<TransformTable> for Transform {
+    type Error = ConfigError;
+
+    fn try_from(table: TransformTable) -> Result<Self, Self::Error> {
+        let regex = Regex::new(&table.pattern)
+            .map_err(|err| ConfigError::InvalidTransform(table.pattern, err))?;
+
+        Ok(Self {
+            regex,
+            replacement: table.replacement,
+        })
+    }
+}
+
+impl Transform {
+    /// Replace all matches of the pattern in the content.
+    pub fn apply<'a>(&self, content: &'a str) -> Cow<'a, str> {
+        self.regex.replace_all(content, self.replacement.as_str())
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(&fs::read_to_string(path)?)?;
+
+        for (name, _, path_arg) in config.commands.all() {
+            if !is_command_name(&name) {
+                return Err(ConfigError::InvalidCommand(name));
+            } else if path_arg == 0 {
+                return Err(ConfigError::InvalidPathArg(name));
+            }
+        }
+        config.files.root_docs()?;
+
+        Ok(config)
+    }
+}
+
+impl CommandsConfig {
+    /// All custom commands with their kind and the position of their path argument.
+    /// A leading backslash is optional and removed from the names.
+    pub fn all(&self) -> impl Iterator<Item = (String, CommandKind, usize)> + '_ {
+        self.input
+            .iter()
+            .map(|name| (name, CommandKind::Input))
+            .chain(
+                self.graphics
+                    .iter()
+                    .map(|name| (name, CommandKind::Graphics)),
+            )
+            .chain(
+                self.bibliography
+                    .iter()
+                    .map(|name| (name, CommandKind::Bibliography)),
+            )
+            .map(|(entry, kind)| {
+                let (name, path_arg) = match entry {
+                    CommandEntry::Name(name) => (name, 1),
+                    CommandEntry::Table(table) => (&table.name, table.path_arg),
+                };
+                (name.trim_start_matches('\\').to_string(), kind, path_arg)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        let config: Config = toml::from_str(
+            r#"
+            [commands]
+            input = ["\\subinput"]
+            graphics = [{ name = "myinclude", path_arg = 2 }]
+            bibliography = ["deptbib"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.commands.all().collect::<Vec<_>>(),
+            vec![
+                ("subinput".to_string(), CommandKind::Input, 1),
+                ("myinclude".to_string(), CommandKind::Graphics, 2),
+                ("deptbib".to_string(), CommandKind::Bibliography, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_transforms() {
+        let config: Config = toml::from_str(
+            r#"
+            [[transforms]]
+            pattern = '\\bf\{([^}]*)\}'
+            replacement = '\textbf{$1}'
+
+            [[transforms]]
+            pattern = 'textbf'
+            replacement = 'emph'
+            "#,
+        )
+        .unwrap();
+
+        let content = config
+            .transforms
+            .iter()
+            .fold(r"\bf{a} and \bf{b}".to_string(), |content, transform| {
+                transform.apply(&content).into_owned()
+            });
+        assert_eq!(content, r"\emph{a} and \emph{b}");
+
+        let invalid = toml::from_str::<Config>("[[transforms]]\npattern = '('\nreplacement = ''");
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_processed_files() {
+        let files = FilesConfig::default();
+
+        assert!(files.is_processed(Path::new("chapters/intro.tex")));
+        assert!(files.is_processed(Path::new("report.rnw")));
+        assert!(!files.is_processed(Path::new("figures/plot.pdf")));
+    }
+
+    #[test]
+    fn test_root_docs() {
+        let root_docs = FilesConfig::default().root_docs().unwrap();
+
+        assert!(root_docs.is_match("README.md"));
+        assert!(root_docs.is_match("license"));
+        assert!(root_docs.is_match("CITATION.cff"));
+        assert!(!root_docs.is_match("docs/README.md"));
+        assert!(!root_docs.is_match("main.tex"));
+    }
+}
@@ -0,0 +1,358 @@
+use crate::{
+    bib, is_processed, is_tex,
+    macros::Macros,
+    mapping::{GraphicsPaths, Mapping},
+    rewrite::{flatten_links, join_continued_commands, trace_imports},
+    scanner::{LineKind, LineScanner},
+    scope::Scope,
+    Args,
+};
+use std::{
+    collections::BTreeSet,
+    fs, mem,
+    path::{Component, Path},
+};
+
+/// Determine if the content is a main document, i.e. it declares a `\documentclass`.
+pub fn is_main_document(content: &str) -> bool {
+    content
+        .lines()
+        .any(|line| strip_comment(line).contains(r"\documentclass"))
+}
+
+/// Remove the comment at the end of the line, if any.
+pub fn strip_comment(line: &str) -> &str {
+    let mut escaped = false;
+
+    for (index, char) in line.char_indices() {
+        match char {
+            '%' if !escaped => return &line[..index],
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+
+    line
+}
+
+/// Remove the comment at the end of a line joined from several lines, if any.
+///
+/// The comments of the earlier lines continue an include command, like `\input{chapters/%`,
+/// so only the comment of the last line is removed.
+pub fn strip_last_comment(line: &str) -> &str {
+    let last_start = line.rfind('\n').map_or(0, |index| index + 1);
+    &line[..last_start + strip_comment(&line[last_start..]).len()]
+}
+
+/// Find the main documents of the project, sorted by path.
+///
+/// If `--main` is given, that is the only main document, whatever its extension.
+/// Otherwise, these are all processed files and files with a `main` extension of the config
+/// declaring a `\documentclass`.
+pub fn main_documents(args: &Args, mapping: &Mapping) -> Vec<String> {
+    if let Some(main) = &args.main {
+        let main = main
+            .components()
+            .filter(|component| *component != Component::CurDir)
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        return vec![main];
+    }
+
+    let mut documents: Vec<_> = mapping
+        .sources()
+        .filter(|source| {
+            is_tex(Path::new(source), args) || args.files.is_main_extension(Path::new(source))
+        })
+        .filter(|source| {
+            fs::read_to_string(args.path.join(source))
+                .is_ok_and(|content| is_main_document(&content))
+        })
+        .map(str::to_string)
+        .collect();
+    documents.sort();
+    documents
+}
+
+/// Collect the project files which are reachable from the roots through include commands,
+/// including the roots themselves.
+pub fn referenced_files(roots: &[String], args: &Args, mapping: &Mapping) -> BTreeSet<String> {
+    let mut referenced = BTreeSet::new();
+    let mut pending: Vec<_> = roots.to_vec();
+
+    while let Some(source) = pending.pop() {
+        if !referenced.insert(source.clone()) {
+            continue;
+        }
+
+        // The attachments of the bibliography entries
+        if args.flatten_bib_files && bib::is_bib(Path::new(&source)) {
+            let Ok(content) = fs::read_to_string(args.path.join(&source)) else {
+                continue;
+            };
+            pending.extend(
+                bib::file_paths(&content)
+                    .iter()
+                    .filter_map(|path| mapping.resolve(path, &[]))
+                    .filter(|target| !referenced.contains(*target))
+                    .map(str::to_string),
+            );
+            continue;
+        }
+
+        if !is_processed(&source, args, mapping) {
+            continue;
+        }
+
+        let Ok(content) = fs::read_to_string(args.path.join(&source)) else {
+            continue;
+        };
+
+        let mut scanner = LineScanner::new(Path::new(&source));
+        let mut scope = Scope::for_file(&source, mapping);
+        // Commented include commands, like `% \input{chapters/old}`, are no references
+        for line in join_continued_commands(&content, args)
+            .into_iter()
+            .filter(|(_, line, _)| scanner.scan(line) == LineKind::Latex)
+            .map(|(_, line, _)| strip_last_comment(line))
+        {
+            scope.define(line, &source, mapping);
+            let mut targets = Vec::new();
+            trace_imports(line, args, mapping, &scope, |import| {
+                targets.extend(import.targets)
+            });
+            for target in targets {
+                scope.include(&target, mapping);
+                if !referenced.contains(&target) {
+                    pending.push(target);
+                }
+            }
+            if args.flatten_href {
+                flatten_links(line, mapping, |target| {
+                    if !referenced.contains(target) {
+                        pending.push(target.to_string());
+                    }
+                });
+            }
+        }
+    }
+
+    referenced
+}
+
+/// Collect the macro definitions of the local class and package files.
+///
+/// Class files often define a project root, like `\def\projroot{./}`,
+/// which the documents use in their include paths.
+pub fn package_macros(args: &Args, mapping: &Mapping) -> Macros {
+    let mut macros = Macros::default();
+
+    let mut packages: Vec<_> = mapping
+        .sources()
+        .filter(|source| {
+            Path::new(source)
+                .extension()
+                .is_some_and(|ext| ext == "cls" || ext == "sty")
+        })
+        .collect();
+    packages.sort();
+
+    for source in packages {
+        let Ok(content) = fs::read_to_string(args.path.join(source)) else {
+            continue;
+        };
+
+        let mut scanner = LineScanner::new(Path::new(source));
+        for line in content
+            .lines()
+            .filter(|line| scanner.scan(line) == LineKind::Latex)
+        {
+            macros.define(line);
+        }
+    }
+
+    macros
+}
+
+/// Determine the `\graphicspath` directories in effect in each file
+/// reachable from the main documents.
+///
+/// Like in LaTeX, an included file starts with the directories set by the including file,
+/// and the directories it sets stay in effect after it is included.
+pub fn graphics_paths(args: &Args, mapping: &Mapping) -> GraphicsPaths {
+    let mut graphics_paths = GraphicsPaths::default();
+
+    for root in main_documents(args, mapping) {
+        visit_graphics_paths(&root, Vec::new(), args, mapping, &mut graphics_paths);
+    }
+
+    graphics_paths
+}
+
+/// Record the graphics paths in effect in the file, continuing with the files it includes.
+///
+/// Returns the graphics paths in effect at the end of the file.
+fn visit_graphics_paths(
+    source: &str,
+    start: Vec<String>,
+    args: &Args,
+    mapping: &Mapping,
+    graphics_paths: &mut GraphicsPaths,
+) -> Vec<String> {
+    if graphics_paths.start.contains_key(source) || !is_processed(source, args, mapping) {
+        // Included again, or a file that can't set graphics paths
+        return graphics_paths.end.get(source).cloned().unwrap_or(start);
+    }
+    graphics_paths
+        .start
+        .insert(source.to_string(), start.clone());
+
+    let mut scope = Scope::with_graphics_paths(start, mapping);
+    if let Ok(content) = fs::read_to_string(args.path.join(source)) {
+        let mut scanner = LineScanner::new(Path::new(source));
+        for line in join_continued_commands(&content, args)
+            .into_iter()
+            .filter(|(_, line, _)| scanner.scan(line) == LineKind::Latex)
+            .map(|(_, line, _)| strip_last_comment(line))
+        {
+            scope.define(line, source, mapping);
+
+            let mut targets = Vec::new();
+            trace_imports(line, args, mapping, &scope, |import| {
+                targets.extend(import.targets)
+            });
+            for target in targets {
+                let start = mem::take(&mut scope.graphics_paths);
+                scope.graphics_paths =
+                    visit_graphics_paths(&target, start, args, mapping, graphics_paths);
+            }
+        }
+    }
+
+    graphics_paths
+        .end
+        .insert(source.to_string(), scope.graphics_paths.clone());
+    scope.graphics_paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_main_document() {
+        assert!(is_main_document(
+            "\\documentclass{article}\n\\begin{document}"
+        ));
+        assert!(!is_main_document(
+            "% \\documentclass{article}\n\\section{Intro}"
+        ));
+        assert!(is_main_document("100\\% \\documentclass{article}"));
+    }
+
+    #[test]
+    fn test_bib_attachments_are_referenced() {
+        let (_dir, mut args, mapping) = crate::project(
+            &[
+                ("main.tex", "\\documentclass{article}\n\\bibliography{refs}"),
+                ("refs.bib", "@misc{scan, file = {:papers/scan.pdf:PDF}}"),
+                ("papers/scan.pdf", ""),
+            ],
+            &[],
+        );
+        let roots = ["main.tex".to_string()];
+        assert!(!referenced_files(&roots, &args, &mapping).contains("papers/scan.pdf"));
+
+        args.flatten_bib_files = true;
+        assert!(referenced_files(&roots, &args, &mapping).contains("papers/scan.pdf"));
+    }
+
+    #[test]
+    fn test_verbatim_inputs_are_not_referenced() {
+        let (_dir, args, mapping) = crate::project(
+            &[
+                (
+                    "main.tex",
+                    "\\documentclass{article}\n\\begin{verbatim}\n\\input{notes}\n\\end{verbatim}",
+                ),
+                ("notes.tex", ""),
+            ],
+            &[],
+        );
+        let referenced = referenced_files(&["main.tex".to_string()], &args, &mapping);
+        assert!(!referenced.contains("notes.tex"));
+    }
+
+    #[test]
+    fn test_commented_inputs_are_not_referenced() {
+        let (_dir, args, mapping) = crate::project(
+            &[
+                (
+                    "main.tex",
+                    "\\documentclass{article}\n% \\input{chapters/old}\n\\input{chapters/new} % \\input{notes}",
+                ),
+                ("chapters/old.tex", ""),
+                ("chapters/new.tex", ""),
+                ("notes.tex", ""),
+            ],
+            &[],
+        );
+        let referenced = referenced_files(&["main.tex".to_string()], &args, &mapping);
+        assert!(referenced.contains("chapters/new.tex"));
+        assert!(!referenced.contains("chapters/old.tex"));
+        assert!(!referenced.contains("notes.tex"));
+    }
+
+    #[test]
+    fn test_inherited_graphics_paths() {
+        let files = [
+            (
+                "main.tex",
+                "\\documentclass{article}\n\\graphicspath{{figures/}}\n\\input{chapters/one}",
+            ),
+            (
+                "chapters/one.tex",
+                "\\input{chapters/two/two}\n\\includegraphics{plot}",
+            ),
+            (
+                "chapters/two/two.tex",
+                "\\graphicspath{{img/}}\n\\input{chapters/two/three}",
+            ),
+            ("chapters/two/three.tex", "\\includegraphics{plot}"),
+            ("chapters/two/img/plot.pdf", ""),
+            ("figures/logo.pdf", ""),
+        ];
+        let (_dir, args, mut mapping) = crate::project(&files, &[]);
+
+        let graphics_paths = graphics_paths(&args, &mapping);
+        assert_eq!(graphics_paths.start["main.tex"], Vec::<String>::new());
+        assert_eq!(graphics_paths.start["chapters/one.tex"], vec!["figures/"]);
+        assert_eq!(
+            graphics_paths.start["chapters/two/two.tex"],
+            vec!["figures/"]
+        );
+        assert_eq!(
+            graphics_paths.start["chapters/two/three.tex"],
+            vec!["chapters/two/img/"]
+        );
+        assert_eq!(graphics_paths.end["main.tex"], vec!["chapters/two/img/"]);
+
+        // The directories set by an included file stay in effect in the including file
+        mapping.set_graphics_paths(graphics_paths);
+        let mut scope = Scope::for_file("chapters/one.tex", &mapping);
+        let mut rewritten = Vec::new();
+        for line in files[1].1.lines() {
+            scope.define(line, "chapters/one.tex", &mapping);
+            let mut targets = Vec::new();
+            let line = trace_imports(line, &args, &mapping, &scope, |import| {
+                targets.extend(import.targets)
+            });
+            rewritten.push(line.into_owned());
+            for target in targets {
+                scope.include(&target, &mapping);
+            }
+        }
+        assert_eq!(rewritten[1], "\\includegraphics{chapters__two__img__plot}");
+    }
+}
@@ -0,0 +1,246 @@
+use serde::{Serialize, Serializer};
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Whether diagnostics are printed as JSON lines, with `--diagnostics json`.
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// The kind of a diagnostic, identified by a stable code like `LF0001`.
+///
+/// The codes are part of the interface for editors and CI filters:
+/// a code is never reused or renumbered, and new kinds get the next free number.
+///
+/// | Code     | Kind                  | Emitted when                                                |
+/// |----------|-----------------------|-------------------------------------------------------------|
+/// | `LF0001` | `UnresolvedReference` | a reference doesn't resolve to a project file, with `--check` |
+/// | `LF0002` | `CaseCollision`       | two names only differ in case                               |
+/// | `LF0003` | `UndefinedMacro`      | a macro in a path is not defined in the file                |
+/// | `LF0004` | `MismatchedExtension` | a reference has the wrong extension, but another file matches |
+/// | `LF0005` | `DuplicateLabel`      | a label is defined twice, with `--check`                    |
+/// | `LF0006` | `InvalidUtf8`         | a processed file is not valid UTF-8                         |
+/// | `LF0007` | `OutsideRoot`         | a reference points outside the project root                 |
+/// | `LF0008` | `AbsoluteMacro`       | a macro in a path expands to an absolute path               |
+/// | `LF0009` | `ProtectedPath`       | a path is protected by a wrapper which can't be removed     |
+/// | `LF0010` | `OversizedTarget`     | a referenced file is larger than `--max-file-size`          |
+/// | `LF0011` | `UnterminatedPath`    | the path of an include command misses its closing brace     |
+/// | `LF0012` | `UnsupportedCommand`  | a command which is not an include command references a file, with `--warn-unsupported` |
+/// | `LF0013` | `Unreferenced`        | a copied file is not referenced from the main document      |
+/// | `LF0014` | `MissingDocument`     | no `\begin{document}` is found, with `--extract-preamble`   |
+/// | `LF0015` | `EngineConflict`      | the project uses features of both XeLaTeX and LuaLaTeX      |
+/// | `LF0016` | `ExactCollision`      | two files are flattened to the same name                    |
+/// | `LF0017` | `DraftRegion`         | a `--strip-draft` marker is nested, not started or not ended |
+/// | `LF0018` | `OversizedFile`       | a file is not copied because it is larger than `--max-file-size` |
+/// | `LF0019` | `OutsideOutput`       | a file would be written outside of the output directory     |
+/// | `LF0020` | `GitDirectory`        | a file would be written into the git directory, with `--git-output` |
+/// | `LF0021` | `NoMainDocument`      | no main document is found, but one is needed               |
+/// | `LF0022` | `MissingMainDocument` | the main document given with `--main` does not exist        |
+/// | `LF0023` | `Timeout`             | the run exceeded `--timeout` and was aborted                |
+/// | `LF0024` | `UnreadableFile`      | the content of a file can't be read to compare or hash it   |
+/// | `LF0025` | `LinkOutsideRoot`     | a project file lies outside the project root through a symbolic link |
+/// | `LF0026` | `RoundTrip`           | a reference of the output doesn't resolve, with `--verify-round-trip` |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    UnresolvedReference,
+    CaseCollision,
+    UndefinedMacro,
+    MismatchedExtension,
+    DuplicateLabel,
+    InvalidUtf8,
+    OutsideRoot,
+    AbsoluteMacro,
+    ProtectedPath,
+    OversizedTarget,
+    UnterminatedPath,
+    UnsupportedCommand,
+    Unreferenced,
+    MissingDocument,
+    EngineConflict,
+    ExactCollision,
+    DraftRegion,
+    OversizedFile,
+    OutsideOutput,
+    GitDirectory,
+    NoMainDocument,
+    MissingMainDocument,
+    Timeout,
+    UnreadableFile,
+    LinkOutsideRoot,
+    RoundTrip,
+}
+
+impl Code {
+    /// The stable code, like `LF0001`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Code::UnresolvedReference => "LF0001",
+            Code::CaseCollision => "LF0002",
+            Code::UndefinedMacro => "LF0003",
+            Code::MismatchedExtension => "LF0004",
+            Code::DuplicateLabel => "LF0005",
+            Code::InvalidUtf8 => "LF0006",
+            Code::OutsideRoot => "LF0007",
+            Code::AbsoluteMacro => "LF0008",
+            Code::ProtectedPath => "LF0009",
+            Code::OversizedTarget => "LF0010",
+            Code::UnterminatedPath => "LF0011",
+            Code::UnsupportedCommand => "LF0012",
+            Code::Unreferenced => "LF0013",
+            Code::MissingDocument => "LF0014",
+            Code::EngineConflict => "LF0015",
+            Code::ExactCollision => "LF0016",
+            Code::DraftRegion => "LF0017",
+            Code::OversizedFile => "LF0018",
+            Code::OutsideOutput => "LF0019",
+            Code::GitDirectory => "LF0020",
+            Code::NoMainDocument => "LF0021",
+            Code::MissingMainDocument => "LF0022",
+            Code::Timeout => "LF0023",
+            Code::UnreadableFile => "LF0024",
+            Code::LinkOutsideRoot => "LF0025",
+            Code::RoundTrip => "LF0026",
+        }
+    }
+}
+
+impl Serialize for Code {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// How severe a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// A file is intentionally left out of the output.
+    Info,
+    /// The output is written, but may not compile as intended.
+    Warning,
+    /// The output can't be written correctly.
+    Error,
+}
+
+/// A problem found in the project, emitted into the report by all passes.
+///
+/// Fatal errors which stop the run right away, like the timeout, are only printed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub code: Code,
+    pub severity: Severity,
+    /// The file it was found in, relative to the project root.
+    pub file: Option<String>,
+    /// The 1-based line number in the file.
+    pub line: Option<usize>,
+    /// The description, without the location.
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn warning(code: Code, message: String) -> Self {
+        Self {
+            code,
+            severity: Severity::Warning,
+            file: None,
+            line: None,
+            message,
+        }
+    }
+
+    pub fn info(code: Code, message: String) -> Self {
+        Self {
+            severity: Severity::Info,
+            ..Self::warning(code, message)
+        }
+    }
+
+    pub fn error(code: Code, message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            ..Self::warning(code, message)
+        }
+    }
+
+    /// Locate the diagnostic in a file.
+    pub fn in_file(mut self, file: impl fmt::Display) -> Self {
+        self.file = Some(file.to_string());
+        self
+    }
+
+    /// Locate the diagnostic on a 1-based line of a file.
+    pub fn at(self, file: impl fmt::Display, line: usize) -> Self {
+        Self {
+            line: Some(line),
+            ..self.in_file(file)
+        }
+    }
+}
+
+/// Print the diagnostics to stderr as one JSON object per line from now on.
+pub fn stream_json() {
+    JSON_OUTPUT.store(true, Ordering::Relaxed);
+}
+
+/// Print the diagnostic to stderr, in the human-readable form or as a JSON line.
+///
+/// When `quiet`, only errors are printed in the human-readable form,
+/// while JSON lines are always printed for the tools consuming them.
+pub fn print(diagnostic: &Diagnostic, quiet: bool) {
+    if JSON_OUTPUT.load(Ordering::Relaxed) {
+        eprintln!("{}", serde_json::to_string(diagnostic).unwrap());
+    } else if !quiet || diagnostic.severity == Severity::Error {
+        eprintln!("{diagnostic}");
+    }
+}
+
+/// The human-readable form, like `Warning [LF0003]: main.tex:4: The macro ...`.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Info => "Info",
+            Severity::Warning => "Warning",
+            Severity::Error => "Error",
+        };
+        write!(f, "{severity} [{}]: ", self.code.as_str())?;
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{file}:{line}: ")?,
+            (Some(file), None) => write!(f, "{file}: ")?,
+            _ => {}
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let diagnostic = Diagnostic::warning(
+            Code::UndefinedMacro,
+            r"The macro \dir is not defined".to_string(),
+        )
+        .at("chapters/a.tex", 4);
+
+        assert_eq!(
+            diagnostic.to_string(),
+            r"Warning [LF0003]: chapters/a.tex:4: The macro \dir is not defined"
+        );
+        assert_eq!(
+            serde_json::to_string(&diagnostic).unwrap(),
+            r#"{"code":"LF0003","severity":"warning","file":"chapters/a.tex","line":4,"message":"The macro \\dir is not defined"}"#
+        );
+        assert_eq!(
+            Diagnostic::error(Code::ExactCollision, "a and b collide".to_string()).to_string(),
+            "Error [LF0016]: a and b collide"
+        );
+        assert_eq!(
+            Diagnostic::info(Code::OversizedFile, "Skipped".to_string())
+                .in_file("video.mp4")
+                .to_string(),
+            "Info [LF0018]: video.mp4: Skipped"
+        );
+    }
+}
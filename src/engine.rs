@@ -0,0 +1,151 @@
+use crate::deps::strip_comment;
+use regex::Regex;
+use serde::Serialize;
+use std::sync::LazyLock;
+
+static PACKAGE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\\(?:usepackage|RequirePackage)[ \t]*(?:\[[^\]]*\])?[ \t]*\{([^{}]*)\}").unwrap()
+});
+static PRIMITIVE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\\(directlua|latelua|luaexec|XeTeX[A-Za-z]*)").unwrap());
+
+/// The packages which only work with XeLaTeX or LuaLaTeX, and which of them they need.
+const PACKAGES: [(&str, Engine); 13] = [
+    ("fontspec", Engine::XelatexOrLualatex),
+    ("polyglossia", Engine::XelatexOrLualatex),
+    ("unicode-math", Engine::XelatexOrLualatex),
+    ("mathspec", Engine::Xelatex),
+    ("xeCJK", Engine::Xelatex),
+    ("xltxtra", Engine::Xelatex),
+    ("xunicode", Engine::Xelatex),
+    ("luacode", Engine::Lualatex),
+    ("luatexja", Engine::Lualatex),
+    ("luatexja-fontspec", Engine::Lualatex),
+    ("luaotfload", Engine::Lualatex),
+    ("luamplib", Engine::Lualatex),
+    ("lualatex-math", Engine::Lualatex),
+];
+
+/// The TeX engine a project needs, inferred from the packages and primitives it uses.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Engine {
+    /// No engine specific packages or primitives are used.
+    #[default]
+    Pdflatex,
+    /// Packages like `fontspec` are used, which work with both Unicode engines.
+    XelatexOrLualatex,
+    /// XeTeX primitives or packages like `xeCJK` are used.
+    Xelatex,
+    /// Lua code or packages like `luacode` are used.
+    Lualatex,
+    /// Both XeLaTeX and LuaLaTeX specific features are used, so no engine supports all of them.
+    Conflicting,
+}
+
+impl Engine {
+    /// The engine which supports the features needing either of the engines.
+    pub fn combine(self, other: Engine) -> Engine {
+        match (self, other) {
+            (Engine::Pdflatex, engine) | (engine, Engine::Pdflatex) => engine,
+            (Engine::XelatexOrLualatex, engine) | (engine, Engine::XelatexOrLualatex) => engine,
+            (first, second) if first == second => first,
+            _ => Engine::Conflicting,
+        }
+    }
+
+    /// The name of the engine, as used in the report.
+    pub fn name(self) -> &'static str {
+        match self {
+            Engine::Pdflatex => "pdflatex",
+            Engine::XelatexOrLualatex => "xelatex-or-lualatex",
+            Engine::Xelatex => "xelatex",
+            Engine::Lualatex => "lualatex",
+            Engine::Conflicting => "conflicting",
+        }
+    }
+}
+
+/// A package or primitive which requires a specific engine.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct EngineHint {
+    /// The file using it, relative to the project root.
+    pub source: String,
+    /// The 1-based line number.
+    pub line: usize,
+    /// The package name, or the primitive with its backslash, like `\directlua`.
+    pub feature: String,
+    /// The engine it requires.
+    pub engine: Engine,
+}
+
+/// Find the packages and primitives of the LaTeX line which require a specific engine,
+/// as pairs of the feature and the engine.
+pub fn find_engine_features(line: &str) -> Vec<(String, Engine)> {
+    let line = strip_comment(line);
+    let mut features = Vec::new();
+
+    for captures in PACKAGE_REGEX.captures_iter(line) {
+        for package in captures[1].split(',').map(str::trim) {
+            if let Some((_, engine)) = PACKAGES.iter().find(|(name, _)| *name == package) {
+                features.push((package.to_string(), *engine));
+            }
+        }
+    }
+
+    for captures in PRIMITIVE_REGEX.captures_iter(line) {
+        let engine = if captures[1].starts_with("XeTeX") {
+            Engine::Xelatex
+        } else {
+            Engine::Lualatex
+        };
+        features.push((captures[0].to_string(), engine));
+    }
+
+    features
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_engine_features() {
+        assert_eq!(
+            find_engine_features(r"\usepackage[no-math]{fontspec,xeCJK} % \directlua"),
+            vec![
+                ("fontspec".to_string(), Engine::XelatexOrLualatex),
+                ("xeCJK".to_string(), Engine::Xelatex),
+            ]
+        );
+        assert_eq!(
+            find_engine_features(r"\RequirePackage{luacode}\directlua{tex.print(1)}"),
+            vec![
+                ("luacode".to_string(), Engine::Lualatex),
+                (r"\directlua".to_string(), Engine::Lualatex),
+            ]
+        );
+        assert!(find_engine_features(r"\usepackage[utf8]{inputenc}").is_empty());
+    }
+
+    #[test]
+    fn test_combine() {
+        assert_eq!(
+            Engine::Pdflatex.combine(Engine::XelatexOrLualatex),
+            Engine::XelatexOrLualatex
+        );
+        assert_eq!(
+            Engine::XelatexOrLualatex.combine(Engine::Lualatex),
+            Engine::Lualatex
+        );
+        assert_eq!(Engine::Xelatex.combine(Engine::Xelatex), Engine::Xelatex);
+        assert_eq!(
+            Engine::Xelatex.combine(Engine::Lualatex),
+            Engine::Conflicting
+        );
+        assert_eq!(
+            Engine::Conflicting.combine(Engine::Pdflatex),
+            Engine::Conflicting
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use std::{fs, io, path::Path};
+
+/// A transparent 1×1 pixel PNG image.
+const PIXEL_PNG: [u8; 67] = [
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f, 0x15, 0xc4,
+    0x89, 0x00, 0x00, 0x00, 0x0a, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x00, 0x01, 0x00, 0x00,
+    0x05, 0x00, 0x01, 0x0d, 0x0a, 0x2d, 0xb4, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae,
+    0x42, 0x60, 0x82,
+];
+
+/// The text files of the sample project, by their path relative to the project root.
+const FILES: [(&str, &str); 4] = [
+    (
+        "main.tex",
+        r"\documentclass{article}
+\usepackage{graphicx}
+
+\title{Sample}
+
+\begin{document}
+\maketitle
+
+\input{chapters/intro}
+\include{chapters/results/index}
+
+\bibliographystyle{plain}
+\bibliography{references}
+\end{document}
+",
+    ),
+    (
+        "chapters/intro.tex",
+        r"\section{Introduction}
+As shown by \cite{knuth1984}, see Figure~\ref{fig:logo}.
+
+\begin{figure}
+    \centering
+    \includegraphics[width=2cm]{figures/logo}
+    \caption{The logo.}
+    \label{fig:logo}
+\end{figure}
+",
+    ),
+    (
+        "chapters/results/index.tex",
+        r"\section{Results}
+\begin{figure}
+    \centering
+    \includegraphics[width=4cm]{figures/plots/result.png}
+    \caption{The result.}
+\end{figure}
+",
+    ),
+    (
+        "references.bib",
+        r"@book{knuth1984,
+    author = {Donald E. Knuth},
+    title = {The {\TeX}book},
+    publisher = {Addison-Wesley},
+    year = {1984},
+}
+",
+    ),
+];
+
+/// The images of the sample project, by their path relative to the project root.
+const IMAGES: [&str; 2] = ["figures/logo.png", "figures/plots/result.png"];
+
+/// Write a small nested LaTeX project to the directory, to try the tool or test it.
+///
+/// It has a main document with chapters in subdirectories, figures and a bibliography.
+/// The directory is created if needed and must be empty.
+pub fn generate(dir: &Path) -> io::Result<()> {
+    if dir.exists() && dir.read_dir()?.next().is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} is not empty", dir.display()),
+        ));
+    }
+
+    let files = FILES
+        .iter()
+        .map(|(source, content)| (*source, content.as_bytes()))
+        .chain(IMAGES.iter().map(|source| (*source, &PIXEL_PNG[..])));
+    write_files(dir, files)
+}
+
+/// Write the files by their path relative to the directory, creating the directories in between.
+pub fn write_files<'a, C: AsRef<[u8]> + 'a>(
+    dir: &Path,
+    files: impl IntoIterator<Item = (&'a str, C)>,
+) -> io::Result<()> {
+    for (source, content) in files {
+        let path = dir.join(source);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, content)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("sample");
+        generate(&project).unwrap();
+
+        assert!(project.join("chapters/results/index.tex").is_file());
+        assert_eq!(
+            fs::read(project.join("figures/logo.png")).unwrap(),
+            PIXEL_PNG
+        );
+        assert!(generate(&project).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Extensions of files which are read by BibTeX/Biber instead of LaTeX.
+///
+/// These never show up in the recorder output, so they are always kept.
+const UNRECORDED_EXTENSIONS: [&str; 2] = ["bib", "bst"];
+
+#[derive(Debug, Error)]
+pub enum FlsError {
+    #[error("Failed to read the recorder file: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// The set of project files that LaTeX opened during a `-recorder` run.
+#[derive(Debug)]
+pub struct RecordedInputs {
+    /// The input paths, relative to the project root.
+    inputs: HashSet<PathBuf>,
+}
+
+impl RecordedInputs {
+    /// Read the `INPUT` lines of the given `.fls` file.
+    pub fn read(fls: &Path, root: &Path) -> Result<Self, FlsError> {
+        let content = fs::read_to_string(fls)?;
+        let root = fs::canonicalize(root)?;
+        // Without a `PWD` line, relative inputs are relative to the location of the recorder file
+        let fls_dir = fs::canonicalize(fls)?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        Ok(Self::parse(&content, &root, &fls_dir))
+    }
+
+    /// Parse the content of a `.fls` file.
+    ///
+    /// `root` and `default_pwd` must be absolute paths.
+    /// Inputs that lie outside of `root`, like the packages of the TeX distribution, are skipped.
+    fn parse(content: &str, root: &Path, default_pwd: &Path) -> Self {
+        let mut pwd = default_pwd.to_path_buf();
+        let mut inputs = HashSet::new();
+
+        for line in content.lines() {
+            if let Some(dir) = line.strip_prefix("PWD ") {
+                pwd = PathBuf::from(dir);
+            } else if let Some(input) = line.strip_prefix("INPUT ") {
+                let path = resolve(&pwd.join(input));
+
+                if let Ok(relative) = path.strip_prefix(root) {
+                    inputs.insert(relative.to_path_buf());
+                }
+            }
+        }
+
+        Self { inputs }
+    }
+
+    /// Determine if the file at the given path, relative to the project root, should be kept.
+    pub fn contains(&self, relative_path: &Path) -> bool {
+        self.inputs.contains(&normalize(relative_path))
+            || relative_path.extension().is_some_and(|ext| {
+                UNRECORDED_EXTENSIONS
+                    .iter()
+                    .any(|unrecorded| ext.eq_ignore_ascii_case(unrecorded))
+            })
+    }
+}
+
+/// Resolve an absolute path, following symlinks if the file still exists.
+fn resolve(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| normalize(path))
+}
+
+/// Lexically remove `.` and `..` components from the path.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_inputs() {
+        let content = "PWD /project\n\
+            INPUT /usr/share/texlive/texmf-dist/tex/latex/base/article.cls\n\
+            INPUT main.tex\n\
+            INPUT ./content/background.tex\n\
+            INPUT /project/figures/plot.pdf\n\
+            OUTPUT main.pdf\n";
+        let recorded = RecordedInputs::parse(content, Path::new("/project"), Path::new("/"));
+
+        assert!(recorded.contains(Path::new("main.tex")));
+        assert!(recorded.contains(Path::new("content/background.tex")));
+        assert!(recorded.contains(Path::new("figures/plot.pdf")));
+        assert!(!recorded.contains(Path::new("main.pdf")));
+        assert!(!recorded.contains(Path::new("content/unused.tex")));
+    }
+
+    #[test]
+    fn test_parse_inputs_without_pwd() {
+        let content = "INPUT ../project/main.tex\n";
+        let recorded = RecordedInputs::parse(content, Path::new("/project"), Path::new("/build"));
+
+        assert!(recorded.contains(Path::new("main.tex")));
+    }
+
+    #[test]
+    fn test_bibliography_always_kept() {
+        let recorded = RecordedInputs::parse("", Path::new("/project"), Path::new("/project"));
+
+        assert!(recorded.contains(Path::new("bibliography/references.bib")));
+    }
+}
@@ -0,0 +1,240 @@
+use crate::{
+    commands::CommandKind,
+    deps::strip_last_comment,
+    is_processed,
+    mapping::Mapping,
+    rewrite::{join_continued_commands, trace_imports},
+    scanner::{LineKind, LineScanner},
+    scope::Scope,
+    Args, MergeOrder,
+};
+use std::{fs, ops::Range, path::Path, vec};
+
+/// Merge the LaTeX files input by the document into it, recursively.
+///
+/// The other references, like graphics and bibliographies, are rewritten to the flattened
+/// names, so the merged document uses the assets of the flattened output.
+/// Inputs of files which are already being inlined are rewritten instead, to break cycles.
+///
+/// With `--sort-merged`, the files input by the document are merged in another order,
+/// see [`merged_files`]. The files they input stay in place.
+pub fn inline_document(
+    source: &str,
+    args: &Args,
+    mapping: &Mapping,
+    manifest: &[String],
+) -> String {
+    let mut stack = vec![source.to_string()];
+    let mut order = (args.sort_merged != MergeOrder::Include)
+        .then(|| merged_files(source, args, mapping, manifest).into_iter());
+    inline_file(source, args, mapping, &mut stack, order.as_mut())
+}
+
+/// The files input by the document, in the order of `--sort-merged`.
+///
+/// They are merged one after another in place of the input commands of the document,
+/// so its other content, like the preamble, stays in place.
+/// The files missing from the `manifest` follow the listed ones, in the order of the inputs.
+fn merged_files(source: &str, args: &Args, mapping: &Mapping, manifest: &[String]) -> Vec<String> {
+    let Ok(content) = fs::read_to_string(args.path.join(source)) else {
+        return Vec::new();
+    };
+
+    let stack = [source.to_string()];
+    let mut scanner = LineScanner::new(Path::new(source));
+    let mut scope = Scope::for_file(source, mapping);
+    let mut files = Vec::new();
+    for (_, line, _) in join_continued_commands(&content, args) {
+        if scanner.scan(line) != LineKind::Latex {
+            continue;
+        }
+        scope.define(line, source, mapping);
+        for (_, _, target) in inlined_inputs(line, args, mapping, &scope, &stack) {
+            scope.include(&target, mapping);
+            files.push(target);
+        }
+    }
+
+    match args.sort_merged {
+        MergeOrder::Include => {}
+        MergeOrder::Alpha => files.sort(),
+        MergeOrder::Manifest => files.sort_by_key(|file| {
+            manifest
+                .iter()
+                .position(|listed| {
+                    mapping.resolve(listed, CommandKind::Input.extensions()) == Some(file.as_str())
+                })
+                .unwrap_or(manifest.len())
+        }),
+    }
+    files
+}
+
+/// Inline the file at the source path, with the files being inlined in `stack`.
+///
+/// The input commands of the file are replaced by the files of `order` one after another,
+/// if given, instead of their own targets.
+fn inline_file(
+    source: &str,
+    args: &Args,
+    mapping: &Mapping,
+    stack: &mut Vec<String>,
+    mut order: Option<&mut vec::IntoIter<String>>,
+) -> String {
+    let Ok(content) = fs::read_to_string(args.path.join(source)) else {
+        return String::new();
+    };
+
+    let mut scanner = LineScanner::new(Path::new(source));
+    let mut scope = Scope::for_file(source, mapping);
+    let lines: Vec<_> = join_continued_commands(&content, args)
+        .into_iter()
+        .map(|(_, line, _)| {
+            if scanner.scan(line) != LineKind::Latex {
+                return line.to_string();
+            }
+            scope.define(line, source, mapping);
+
+            let mut inlined = String::new();
+            let mut end = 0;
+            for (range, command, target) in inlined_inputs(line, args, mapping, &scope, stack) {
+                inlined.push_str(&trace_imports(
+                    &line[end..range.start],
+                    args,
+                    mapping,
+                    &scope,
+                    |_| {},
+                ));
+
+                let target = order
+                    .as_mut()
+                    .and_then(|order| order.next())
+                    .unwrap_or(target);
+                stack.push(target.clone());
+                let content = inline_file(&target, args, mapping, stack, None);
+                stack.pop();
+                if command == "include" {
+                    // Like `\include`, start the content on a new page
+                    inlined.push_str(&format!("\\clearpage\n{content}\n\\clearpage"));
+                } else {
+                    inlined.push_str(&content);
+                }
+
+                scope.include(&target, mapping);
+                end = range.end;
+            }
+            inlined.push_str(&trace_imports(&line[end..], args, mapping, &scope, |_| {}));
+            inlined
+        })
+        .collect();
+
+    lines.join("\n")
+}
+
+/// The input commands of the line which are replaced by the content of their target,
+/// with the range of the command, its name and the target, sorted by position.
+fn inlined_inputs(
+    line: &str,
+    args: &Args,
+    mapping: &Mapping,
+    scope: &Scope,
+    stack: &[String],
+) -> Vec<(Range<usize>, String, String)> {
+    let mut inputs = Vec::new();
+
+    for regex in args.commands.import_regexes() {
+        for capture in regex.captures_iter(strip_last_comment(line)) {
+            let command = capture.get(1).map_or("input", |mat| mat.as_str());
+            if args.commands.kind(command) != Some(CommandKind::Input) {
+                continue;
+            }
+
+            let whole = capture.get(0).unwrap();
+            let mut targets = Vec::new();
+            trace_imports(whole.as_str(), args, mapping, scope, |import| {
+                targets.extend(import.targets)
+            });
+            if let [target] = &targets[..] {
+                if is_processed(target, args, mapping) && !stack.contains(target) {
+                    inputs.push((whole.range(), command.to_string(), target.clone()));
+                }
+            }
+        }
+    }
+
+    inputs.sort_by_key(|(range, _, _)| range.start);
+    inputs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_document() {
+        let (_dir, args, mapping) = crate::project(
+            &[
+            (
+                "main.tex",
+                "\\documentclass{article}\n\\input{chapters/intro} % intro\n\\include{chapters/outro}\n\\includegraphics{figures/plot}",
+            ),
+            ("chapters/intro.tex", "Intro \\input{chapters/main-ref} % \\input{chapters/old}"),
+            ("chapters/main-ref.tex", "\\input{main}"),
+            ("chapters/outro.tex", "\\begin{verbatim}\n\\input{x}\n\\end{verbatim}"),
+            ("chapters/old.tex", "Old"),
+            ("figures/plot.pdf", ""),
+            ],
+            &[],
+        );
+
+        assert_eq!(
+            inline_document("main.tex", &args, &mapping, &[]),
+            "\\documentclass{article}\n\
+             Intro \\input{main} % \\input{chapters/old} % intro\n\
+             \\clearpage\n\\begin{verbatim}\n\\input{x}\n\\end{verbatim}\n\\clearpage\n\
+             \\includegraphics{figures__plot}"
+        );
+    }
+
+    #[test]
+    fn test_sort_merged() {
+        let files = [
+            (
+                "main.tex",
+                "\\documentclass{article}\n\\input{c}\n\\input{a}\nText\n\\input{b}",
+            ),
+            ("a.tex", "A"),
+            ("b.tex", "B \\input{d}"),
+            ("c.tex", "C"),
+            ("d.tex", "D"),
+        ];
+        let merge = |flags: &[&str], manifest: &[&str]| {
+            let (_dir, args, mapping) = crate::project(&files, flags);
+            let manifest: Vec<_> = manifest.iter().map(|line| line.to_string()).collect();
+            inline_document("main.tex", &args, &mapping, &manifest)
+        };
+
+        assert_eq!(merge(&[], &[]), "\\documentclass{article}\nC\nA\nText\nB D");
+        assert_eq!(
+            merge(
+                &["--also-inline", "merged.tex", "--sort-merged", "alpha"],
+                &[]
+            ),
+            "\\documentclass{article}\nA\nB D\nText\nC"
+        );
+        assert_eq!(
+            merge(
+                &[
+                    "--also-inline",
+                    "merged.tex",
+                    "--sort-merged",
+                    "manifest",
+                    "--merge-order",
+                    "order.txt"
+                ],
+                &["b", "c.tex"]
+            ),
+            "\\documentclass{article}\nB D\nC\nText\nA"
+        );
+    }
+}
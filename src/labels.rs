@@ -0,0 +1,29 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+static LABEL_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\\label[ \t]*\{([^}]*)\}").unwrap());
+
+/// Find the keys of the `\label` commands in the line, ignoring a trailing comment.
+pub fn find_labels(line: &str) -> Vec<&str> {
+    let line = crate::deps::strip_comment(line);
+
+    LABEL_REGEX
+        .captures_iter(line)
+        .map(|capture| capture.get(1).unwrap().as_str().trim())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_labels() {
+        assert_eq!(
+            find_labels(r"\section{Intro}\label{sec:intro} \label {fig:plot} % \label{old}"),
+            vec!["sec:intro", "fig:plot"]
+        );
+        assert!(find_labels(r"\ref{sec:intro}").is_empty());
+    }
+}
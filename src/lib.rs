@@ -0,0 +1,3567 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use commands::{
+    CommandKind, Commands, BIBLIOGRAPHY_EXTENSIONS, BIBLIOGRAPHY_STYLE_EXTENSIONS,
+    GRAPHICS_EXTENSIONS,
+};
+use config::{Config, FilesConfig, PackagesConfig, Transform};
+use diagnostic::{Code, Diagnostic};
+use engine::{Engine, EngineHint};
+use fls::RecordedInputs;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use indicatif::{ProgressBar, ProgressStyle};
+use mapping::{CollisionKind, Mapping};
+use output::{Compression, Output};
+use report::{FileEntry, ImportEntry, Report};
+use rewrite::{
+    explain_imports, find_unsupported_commands, find_unterminated_imports, flatten_links,
+    join_continued_commands, trace_imports, Import, SkipReason,
+};
+use scanner::{LineKind, LineScanner};
+use scope::Scope;
+use sha2::{Digest, Sha256};
+use stats::DirectoryStats;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    env,
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, IsTerminal, Read, Seek, SeekFrom, Write},
+    iter,
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+    slice,
+    time::Duration,
+};
+use template::{RenameTemplate, TemplateError};
+use thiserror::Error;
+use timeout::exit;
+use unicode_normalization::UnicodeNormalization;
+use walkdir::{DirEntry, WalkDir};
+
+mod archive;
+mod bib;
+mod commands;
+mod config;
+mod deps;
+mod diagnostic;
+mod engine;
+mod fixture;
+mod fls;
+mod inline;
+mod labels;
+mod macros;
+mod mapping;
+mod mirror;
+mod output;
+mod packages;
+mod prompt;
+mod prune;
+mod report;
+mod rewrite;
+mod scanner;
+mod scope;
+mod size;
+mod stats;
+mod template;
+mod timeout;
+mod tree;
+mod verify;
+
+/// Whether rewritten `\input` and `\include` references carry the `.tex` extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ReferenceExtension {
+    /// Keep the extension as written in the reference
+    Keep,
+    /// Always omit the extension
+    Strip,
+    /// Always write the extension
+    Add,
+}
+
+/// The case of the flattened names
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum FlattenCase {
+    /// Lowercase the names
+    Lower,
+    /// Keep the case of the source paths
+    #[default]
+    Keep,
+}
+
+impl FlattenCase {
+    /// Apply the case to a flattened name.
+    fn apply(self, name: String) -> String {
+        match self {
+            FlattenCase::Lower => name.to_lowercase(),
+            FlattenCase::Keep => name,
+        }
+    }
+}
+
+/// How files with conflicting output names are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OnCollision {
+    /// Fail on exact collisions and warn about collisions in case
+    Error,
+    /// Ask for a new name for each colliding file
+    Interactive,
+}
+
+/// The line endings of the processed files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LineEnding {
+    /// Use `\n`
+    Lf,
+    /// Use `\r\n`
+    Crlf,
+    /// Keep the line endings as written
+    Preserve,
+}
+
+impl LineEnding {
+    /// Apply the line ending to all lines of the content.
+    fn apply(self, content: String) -> String {
+        match self {
+            LineEnding::Lf => content.replace("\r\n", "\n"),
+            LineEnding::Crlf => content.replace("\r\n", "\n").replace('\n', "\r\n"),
+            LineEnding::Preserve => content,
+        }
+    }
+}
+
+/// The order of the files merged into the document with `--also-inline`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum MergeOrder {
+    /// The order of the input commands in the main document
+    Include,
+    /// The alphabetical order of the file paths
+    Alpha,
+    /// The order of the files listed in the file given with `--merge-order`
+    Manifest,
+}
+
+/// The formats of the machine-readable report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ReportFormat {
+    Json,
+}
+
+/// The formats of the diagnostics stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum DiagnosticsFormat {
+    /// One JSON object per line with the fields `code`, `severity`, `file`, `line` and `message`
+    Json,
+}
+
+/// How `--split-output` writes each document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SplitOutput {
+    /// A directory per document
+    Dir,
+    /// A zip archive per document
+    Zip,
+}
+
+/// The output name of the preamble extracted with `--extract-preamble`.
+const PREAMBLE_NAME: &str = "preamble.tex";
+
+/// The supported types of input and output
+#[derive(Debug, PartialEq, Eq)]
+enum FileType {
+    Directory,
+    Zip,
+}
+
+#[derive(Debug, Error)]
+enum FileTypeError {
+    #[error("Invalid extension .{0:?}, expected zip file or directory")]
+    InvalidExtension(OsString),
+}
+
+/// The commands besides flattening a project
+#[derive(Debug, Subcommand)]
+enum ToolCommand {
+    /// Write a small nested LaTeX project to the directory, to try the tool or to seed tests
+    #[command(hide = true)]
+    GenerateFixture {
+        /// The directory to write the project to, which must be empty
+        dir: PathBuf,
+    },
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
+pub struct Args {
+    #[command(subcommand)]
+    command: Option<ToolCommand>,
+
+    /// The path of the folder containing the LaTeX project
+    #[arg(
+        short,
+        long,
+        required_unless_present = "list_commands",
+        default_value = ".",
+        hide_default_value = true
+    )]
+    path: Box<Path>,
+
+    /// The path of the directory where the new project will be created
+    #[arg(
+        short,
+        long,
+        required_unless_present = "list_commands",
+        default_value = ".",
+        hide_default_value = true
+    )]
+    out: Box<Path>,
+
+    /// The directory the flattened names are computed from, defaulting to `--path`.
+    /// Must be an ancestor of every processed file
+    #[arg(long)]
+    relative_to: Option<Box<Path>>,
+
+    /// Name `index.tex` files after their directory, e.g. `chapters/intro/index.tex` becomes
+    /// `chapters__intro.tex`. Not done if a file like `chapters/intro.tex` exists
+    #[arg(long)]
+    collapse_index: bool,
+
+    /// A TOML configuration file.
+    /// Its `[commands]` section registers custom `input`, `graphics` and `bibliography` commands,
+    /// its `[files]` section sets the `processed` extensions, by default `tex`, `Rnw` and `dtx`,
+    /// and the `root_docs` globs of `--include-root-docs`,
+    /// its `[packages]` section lists additional `system` packages,
+    /// its `[[transforms]]` entries find and replace a `pattern` in the processed files
+    #[arg(long)]
+    config: Option<Box<Path>>,
+
+    /// Only process the files matching the glob, relative to the project root.
+    /// Can be given multiple times
+    #[arg(long)]
+    include: Vec<Glob>,
+
+    /// Also process the files referenced by the included files, transitively
+    #[arg(long, requires = "include")]
+    follow_deps: bool,
+
+    /// Always copy the documentation files in the project root, like `README.md`, `LICENSE`
+    /// and `CITATION.cff`, even if `--include` or `--fls` leave them out.
+    /// The globs can be set with `root_docs` in the `[files]` section of the config file
+    #[arg(long)]
+    include_root_docs: bool,
+
+    /// A command whose arguments are never rewritten, like `url`, even if it is
+    /// an include command or registered in the config file. Can be given multiple times
+    #[arg(long, value_parser = commands::parse_command_name)]
+    preserve_command: Vec<String>,
+
+    /// Print the recognized include commands, including the ones from the config file, and exit
+    #[arg(long)]
+    list_commands: bool,
+
+    /// The `.fls` file of a `latex -recorder` run.
+    /// Only the files recorded as inputs will be copied
+    #[arg(long)]
+    fls: Option<Box<Path>>,
+
+    /// The main document, relative to the project root, which is processed whatever its extension,
+    /// e.g. `paper.ltx` or `paper`. By default, all processed files declaring a `\documentclass`
+    /// are main documents, as are the files with a `main` extension of the `[files]` config
+    #[arg(long)]
+    main: Option<Box<Path>>,
+
+    /// The output name of the main document, e.g. `ms.tex`.
+    /// References to it are updated accordingly
+    #[arg(long, value_parser = parse_main_out_name)]
+    main_out_name: Option<String>,
+
+    /// Move the preamble of the main document, everything before `\begin{document}`,
+    /// to `preamble.tex` and input it in its place. The include commands of the preamble
+    /// are rewritten as well
+    #[arg(long)]
+    extract_preamble: bool,
+
+    /// Delete the files not referenced from the main document in place, instead of flattening.
+    /// Requires `--out` to be the same as `--path` and the project to be under version control.
+    /// Lists the files and asks for confirmation first, unless `--yes` is given
+    #[arg(long)]
+    in_place: bool,
+
+    /// With `--in-place`, only rewrite the references of the LaTeX files of an already flat
+    /// directory, instead of pruning. A file like `chapters__intro.tex` is taken to be
+    /// `chapters/intro.tex`, so `\input{chapters/intro}` becomes `\input{chapters__intro}`.
+    /// No files are moved
+    #[arg(long, requires = "in_place")]
+    rewrite_only: bool,
+
+    /// Allow `--in-place` for projects which are not under version control
+    #[arg(long, requires = "in_place")]
+    in_place_force: bool,
+
+    /// Flatten each main document separately with the files it references,
+    /// into `<out>/<document>/` or `<out>/<document>.zip`.
+    /// Collisions are only checked between the files of the same document
+    #[arg(
+        long,
+        value_enum,
+        conflicts_with_all = [
+            "main",
+            "main_out_name",
+            "extract_preamble",
+            "in_place",
+            "check",
+            "copy_only_referenced",
+            "fail_if_unreferenced",
+            "verify_round_trip",
+        ]
+    )]
+    split_output: Option<SplitOutput>,
+
+    /// Don't copy the files larger than the given size, like `10MB` or `512KiB`.
+    /// References to them are reported, as they dangle in the output
+    #[arg(long, value_parser = size::parse_size)]
+    max_file_size: Option<u64>,
+
+    /// Also write the main document with the LaTeX files it inputs merged into it
+    /// to the given file, e.g. to read the whole document in one window.
+    /// Its other references use the flattened names, so it shares the assets of the output
+    #[arg(
+        long,
+        conflicts_with_all = ["in_place", "check", "copy_only_referenced", "split_output", "dry_run", "bib_only"]
+    )]
+    also_inline: Option<PathBuf>,
+
+    /// The order of the files input by the main document in the merged document of `--also-inline`.
+    /// The files they input stay in place
+    #[arg(long, value_enum, default_value_t = MergeOrder::Include, requires = "also_inline")]
+    sort_merged: MergeOrder,
+
+    /// The file listing the files input by the main document in the order to merge them
+    /// with `--sort-merged manifest`, one path relative to the project root per line.
+    /// Files which are not listed follow the listed ones
+    #[arg(
+        long,
+        required_if_eq("sort_merged", "manifest"),
+        requires = "also_inline"
+    )]
+    merge_order: Option<PathBuf>,
+
+    /// Update the existing output zip file instead of creating it.
+    /// Entries whose content is unchanged are kept without compressing them again,
+    /// and entries whose sources disappeared are removed
+    #[arg(
+        long,
+        conflicts_with_all = ["in_place", "check", "split_output", "dry_run", "fail_if_unreferenced"]
+    )]
+    update_archive: bool,
+
+    /// Write into an output directory which is a git repository, like a committed mirror.
+    /// The directory may already contain files, which are overwritten, and `.git` is never touched.
+    /// A `.gitattributes` marking the binary assets and a `.gitignore` of the build files
+    /// are written unless they exist, and the added and changed files are printed
+    #[arg(
+        long,
+        conflicts_with_all = ["in_place", "check", "split_output", "dry_run", "fail_if_unreferenced"]
+    )]
+    git_output: bool,
+
+    /// The size of the buffer files which are not rewritten are copied through,
+    /// like `1MiB`. Larger buffers can speed up copying large assets
+    #[arg(long, value_parser = size::parse_buffer_size, default_value = "64KiB")]
+    copy_buffer_size: usize,
+
+    /// Abort the run if it takes longer than the given duration, like `90s` or `5m`.
+    /// The partial output is removed, so no incomplete output is left behind
+    #[arg(long, value_parser = timeout::parse_duration)]
+    timeout: Option<Duration>,
+
+    /// Print the flattened name of every file instead of writing the output
+    #[arg(long, conflicts_with_all = ["in_place", "check", "copy_only_referenced", "split_output"])]
+    dry_run: bool,
+
+    /// Show the `--dry-run` mapping as the tree of the project, with an arrow to each name
+    #[arg(long, requires = "dry_run")]
+    tree: bool,
+
+    /// Never color the output. Colors are also disabled when stdout is not a terminal
+    /// or `NO_COLOR` is set
+    #[arg(long)]
+    no_color: bool,
+
+    /// Print the number of files and bytes per top-level directory of the project,
+    /// to see where its bulk comes from
+    #[arg(long, conflicts_with_all = ["in_place", "check", "fail_if_unreferenced"])]
+    stats: bool,
+
+    /// Delete the files of `--in-place` without asking for confirmation.
+    /// Required when not running interactively
+    #[arg(short, long, requires = "in_place")]
+    yes: bool,
+
+    /// Rewrite graphics references whose stated extension doesn't exist
+    /// to the file with the same name and another extension, e.g. `plot.eps` to `plot.pdf`
+    #[arg(long)]
+    fix_extensions: bool,
+
+    /// Normalize the flattened names, and the references to them, to Unicode NFC.
+    /// Decomposed names, as created on macOS, then match composed references
+    #[arg(long)]
+    normalize_unicode: bool,
+
+    /// The case of the flattened names, and of the references to them.
+    /// Names which only differ in case then collide
+    #[arg(long, value_enum, default_value_t = FlattenCase::Keep)]
+    flatten_case: FlattenCase,
+
+    /// Only flatten the file names, copying the content of LaTeX files unchanged
+    #[arg(long, conflicts_with_all = ["strip_trailing_whitespace", "collapse_blank_lines", "strip_draft", "fix_extensions", "normalize_eol"])]
+    no_rewrite: bool,
+
+    /// Remove trailing whitespace from the lines of LaTeX files, except in verbatim environments
+    #[arg(long)]
+    strip_trailing_whitespace: bool,
+
+    /// The line endings of the rewritten LaTeX files. Other files are copied unchanged
+    #[arg(long, value_enum, default_value_t = LineEnding::Preserve)]
+    normalize_eol: LineEnding,
+
+    /// Collapse runs of blank lines in LaTeX files into a single blank line,
+    /// except in verbatim environments
+    #[arg(long)]
+    collapse_blank_lines: bool,
+
+    /// Remove the draft regions of LaTeX files, from a `%DRAFT-BEGIN` comment line
+    /// to a `%DRAFT-END` comment line, including the markers
+    #[arg(long)]
+    strip_draft: bool,
+
+    /// The comment marking the start of a draft region for `--strip-draft`, without the `%`
+    #[arg(long, default_value = "DRAFT-BEGIN", requires = "strip_draft")]
+    draft_begin: String,
+
+    /// The comment marking the end of a draft region for `--strip-draft`, without the `%`
+    #[arg(long, default_value = "DRAFT-END", requires = "strip_draft")]
+    draft_end: String,
+
+    /// Copy the files outside of the project root that are referenced, like `../shared/logo.pdf`,
+    /// into the output as `external__shared__logo.pdf`. Without it, absolute references like
+    /// `/home/me/fig.pdf` or `C:/Users/me/fig.pdf` are left unchanged
+    #[arg(long, conflicts_with_all = ["in_place", "copy_only_referenced"])]
+    include_external_refs: bool,
+
+    /// List the files not referenced from the main document and fail if there are any,
+    /// without writing the output
+    #[arg(long, conflicts_with_all = ["in_place", "copy_only_referenced"])]
+    fail_if_unreferenced: bool,
+
+    /// Don't warn about the copied figures, bibliographies and LaTeX files
+    /// which are not referenced from the main document
+    #[arg(long)]
+    no_unreferenced_warnings: bool,
+
+    /// Validate that the project can be flattened without writing anything.
+    /// Reports unresolved references, collisions and duplicate labels and fails if there are any
+    #[arg(long, conflicts_with_all = ["in_place", "copy_only_referenced", "fail_if_unreferenced"])]
+    check: bool,
+
+    /// After flattening, check that every reference of the output resolves to an output file
+    /// and contains no path separator. Fails if there are any violations
+    #[arg(long, conflicts_with_all = ["in_place", "copy_only_referenced", "check"])]
+    verify_round_trip: bool,
+
+    /// Only copy the files referenced from the main document, keeping the directory structure
+    /// and leaving the references untouched
+    #[arg(long, conflicts_with = "in_place")]
+    copy_only_referenced: bool,
+
+    /// Flatten the paths of the attachments in the `file` fields of `.bib` files,
+    /// like `file = {papers/scan.pdf}` or JabRef's `:papers/scan.pdf:PDF`.
+    /// The attachments count as referenced by the bibliography, so they are also copied
+    /// with `--copy-only-referenced`
+    #[arg(long)]
+    flatten_bib_files: bool,
+
+    /// Rewrite the targets of `\href` and `\url` which are project files, like
+    /// `\href{docs/appendix.pdf}{Appendix}` or `\href{run:./docs/appendix.pdf}{Appendix}`,
+    /// to their flattened names. The files count as referenced, so they are also copied
+    /// with `--copy-only-referenced`. URLs like `https://...` are left unchanged
+    #[arg(long)]
+    flatten_href: bool,
+
+    /// Only copy the `.bib` and `.bst` files referenced from the main document
+    /// with flattened names, e.g. to import them into a reference manager
+    #[arg(
+        long,
+        conflicts_with_all = ["in_place", "check", "copy_only_referenced", "split_output", "dry_run", "verify_round_trip"]
+    )]
+    bib_only: bool,
+
+    /// Warn about the commands which are not include commands, but whose single argument
+    /// looks like the path of a project file, like `\myfig{figures/plot}`.
+    /// These can be registered in the `[commands]` section of the config file
+    #[arg(long)]
+    warn_unsupported: bool,
+
+    /// Print every matched include command and how it was rewritten to stderr
+    #[arg(long)]
+    explain: bool,
+
+    /// Write the assets, the files which are not LaTeX source or bibliographies,
+    /// flattened into the given directory of the output, e.g. `assets`.
+    /// Their references become `assets/figures__plot.pdf`, while the LaTeX files stay at the root
+    #[arg(long, value_parser = parse_asset_dir, conflicts_with = "in_place")]
+    asset_dir: Option<String>,
+
+    /// Copy byte-identical assets only once and point all references to the same file
+    #[arg(long)]
+    dedupe_assets: bool,
+
+    /// Name the assets after their file name only, e.g. `logo.png` for `a/logo.png`.
+    /// Assets with the same name and content are copied once, with all references pointing
+    /// to that copy. Assets with the same name but different content collide
+    #[arg(long)]
+    merge_assets_by_basename: bool,
+
+    /// Follow symbolic links to directories, also outside of the project root,
+    /// e.g. `figures -> ../shared-figures`. A file reached through several paths is copied once,
+    /// and all references point to the same file
+    #[arg(long, conflicts_with = "in_place")]
+    follow_symlinks: bool,
+
+    /// Whether rewritten `\input` and `\include` references carry the `.tex` extension.
+    /// The files on disk always keep their extension
+    #[arg(long, value_enum, default_value_t = ReferenceExtension::Keep)]
+    reference_extension: ReferenceExtension,
+
+    /// A template for the flattened file names, e.g. `{dir}{stem}-{hash}.{ext}`.
+    /// Available variables are `{dir}` (the directories, each followed by `__`), `{parent}`, `{stem}`,
+    /// `{ext}` and `{hash}` (a short hash of the content); append `:lower` or `:upper` to change the case
+    #[arg(long, value_parser = RenameTemplate::parse)]
+    rename_template: Option<RenameTemplate>,
+
+    /// Prefix the flattened names by the kind of file, so different kinds never collide:
+    /// `tex__` for LaTeX files, `fig__` for graphics and `bib__` for bibliographies,
+    /// e.g. `fig__figures__plot.pdf`. The prefix comes before the name of `--rename-template`
+    /// and `--merge-assets-by-basename`, and inside of `--asset-dir`.
+    /// References which don't resolve to a project file are left unchanged
+    #[arg(long)]
+    namespace_by_type: bool,
+
+    /// How files with conflicting output names are handled. `interactive` asks for a new name
+    /// for each colliding file, suggesting one with a hash of its content, and requires a terminal
+    #[arg(long, value_enum, default_value_t = OnCollision::Error)]
+    on_collision: OnCollision,
+
+    /// Don't print warnings
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Fail with a nonzero exit code if there were any warnings
+    #[arg(long)]
+    strict: bool,
+
+    /// Show a progress bar with the throughput and the estimated remaining time
+    #[arg(long)]
+    progress: bool,
+
+    /// Print a machine-readable report of the processed files and warnings
+    #[arg(long, value_enum)]
+    report: Option<ReportFormat>,
+
+    /// Print the warnings and errors to stderr in a machine-readable format instead,
+    /// even with `--quiet`. Each has a stable code like `LF0003`
+    #[arg(long, value_enum)]
+    diagnostics: Option<DiagnosticsFormat>,
+
+    /// Write the report to the given file instead of stdout
+    #[arg(long)]
+    report_file: Option<Box<Path>>,
+
+    /// The number of threads, e.g. for extracting zip input. Defaults to the number of CPUs
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    /// The compression of zip output, from 0 (fastest) to 9 (smallest) or `store`.
+    /// Defaults to 6
+    #[arg(long, value_parser = Compression::parse)]
+    compression: Option<Compression>,
+
+    /// A shell command to run after writing the output, in the output directory.
+    /// The output path is available as `LATEX_FLATTEN_OUT`. The run fails if the command fails
+    #[arg(long)]
+    post_hook: Option<String>,
+
+    /// Write the include commands as CSV with the columns `from,to,command,rewritten`,
+    /// with one row per referenced file
+    #[arg(long)]
+    manifest_csv: Option<Box<Path>>,
+
+    /// Record the SHA-256 digest of every written file in the report and the CSV manifest,
+    /// to verify the integrity of the output
+    #[arg(long)]
+    manifest_hashes: bool,
+
+    /// Run non-interactively for continuous integration.
+    /// This is a shorthand for `--quiet --strict --report json`
+    #[arg(long)]
+    ci: bool,
+
+    /// The recognized include commands, including the ones from the config file
+    #[arg(skip)]
+    commands: Commands,
+
+    /// The processed files, from the config file
+    #[arg(skip)]
+    files: FilesConfig,
+
+    /// The additional distribution packages, from the config file
+    #[arg(skip)]
+    packages: PackagesConfig,
+
+    /// The find and replace transforms of the processed files, from the config file
+    #[arg(skip)]
+    transforms: Vec<Transform>,
+}
+
+/// Run the command line interface with the arguments of the process.
+pub fn run() {
+    let mut args = Args::parse();
+    if let Some(ToolCommand::GenerateFixture { dir }) = &args.command {
+        fixture::generate(dir).unwrap_or_else(|err| {
+            eprintln!("Failed to generate the fixture: {err}");
+            exit(1);
+        });
+        return;
+    }
+
+    if let Some(DiagnosticsFormat::Json) = args.diagnostics {
+        diagnostic::stream_json();
+    }
+
+    if let Some(timeout) = args.timeout {
+        timeout::start(timeout);
+    }
+
+    if let Some(path) = &args.config {
+        let config = Config::load(path).unwrap_or_else(|err| {
+            eprintln!("Config: {err}");
+            exit(1);
+        });
+        args.commands = Commands::with_custom_path_args(config.commands.all());
+        args.files = config.files;
+        args.packages = config.packages;
+        args.transforms = config.transforms;
+
+        if args.no_rewrite && !args.transforms.is_empty() {
+            eprintln!("--no-rewrite can't be used with the [[transforms]] of the config file");
+            exit(1);
+        }
+    }
+
+    if args.list_commands {
+        for (name, kind) in args.commands.iter() {
+            let name = if name == "bibliography" {
+                // Also covers the variants of the multibib package
+                "bibliography*"
+            } else {
+                name
+            };
+            match args.commands.path_arg(name) {
+                1 => println!("\\{name:<20} {}", kind.name()),
+                path_arg => println!("\\{name:<20} {} (path argument {path_arg})", kind.name()),
+            }
+        }
+        return;
+    }
+
+    if args.ci {
+        args.quiet = true;
+        args.strict = true;
+        args.report.get_or_insert(ReportFormat::Json);
+    }
+
+    let input_path = &args.path;
+    let input_type = path_file_type(input_path).unwrap_or_else(|err| {
+        eprintln!("Input: {err}");
+        exit(1);
+    });
+
+    // Sanity checks
+    match input_type {
+        FileType::Directory => {
+            if !input_path.is_dir() {
+                eprintln!("The input path must point to a directory");
+                exit(1);
+            }
+        }
+        FileType::Zip => {
+            if !input_path.is_file() {
+                eprintln!("The input path must point to a zip file");
+                exit(1);
+            }
+        }
+    }
+
+    if let Some(jobs) = args.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()
+            .unwrap();
+    }
+
+    if args.in_place && input_type != FileType::Directory {
+        eprintln!("--in-place requires the input to be a directory");
+        exit(1);
+    }
+
+    // Archives are extracted into a temporary directory, which is then processed like a project
+    // directory. It is deleted when it goes out of scope at the end of the run,
+    // or by `timeout::exit` when the run fails early
+    let _extracted = (input_type == FileType::Zip).then(|| {
+        let dir = tempfile::tempdir().unwrap_or_else(|err| {
+            eprintln!("Failed to create a temporary directory: {err}");
+            exit(1);
+        });
+        timeout::remove_on_exit(dir.path().into());
+        archive::extract_zip(&args.path, dir.path()).unwrap_or_else(|err| {
+            eprintln!("Input: {err}");
+            exit(1);
+        });
+        args.path = dir.path().into();
+        dir
+    });
+
+    let report = Report::new(args.quiet);
+
+    let recorded_inputs = args.fls.as_ref().map(|fls| {
+        RecordedInputs::read(fls, &args.path).unwrap_or_else(|err| {
+            eprintln!("Fls: {err}");
+            exit(1);
+        })
+    });
+
+    if args.fail_if_unreferenced {
+        check_unreferenced(
+            &walk_project(&args, recorded_inputs.as_ref(), &report),
+            &args,
+            &report,
+        );
+        return;
+    }
+
+    if args.check {
+        let has_exact_collision = check_project(
+            &walk_project(&args, recorded_inputs.as_ref(), &report),
+            &args,
+            &report,
+        );
+
+        if has_exact_collision || report.warning_count() > 0 || report.error_count() > 0 {
+            eprintln!("The project can't be flattened cleanly");
+            exit(1);
+        }
+        return;
+    }
+
+    if args.in_place && args.rewrite_only {
+        rewrite_flat_project(
+            &walk_project(&args, recorded_inputs.as_ref(), &report),
+            &args,
+            &report,
+        );
+        exit_on_errors(&report);
+        return;
+    }
+
+    if args.in_place {
+        prune_project(
+            &walk_project(&args, recorded_inputs.as_ref(), &report),
+            &args,
+            &report,
+        );
+        return;
+    }
+
+    if args.dry_run {
+        let entries = walk_project(&args, recorded_inputs.as_ref(), &report);
+        let mapping = build_mapping(&entries, &args, &report);
+        check_collisions(&mapping, &report);
+        print_dry_run(&entries, &args, &mapping);
+        if args.stats {
+            print_stats(project_stats(&entries, &args), &report);
+        }
+        return;
+    }
+
+    let output_path = &args.out;
+    let output_type = path_file_type(output_path).unwrap_or_else(|err| {
+        eprintln!("Output: {err}");
+        exit(1);
+    });
+
+    if args.compression.is_some() && output_type != FileType::Zip {
+        eprintln!("--compression requires the output to be a zip file");
+        exit(1);
+    }
+
+    if args.update_archive && output_type != FileType::Zip {
+        eprintln!("--update-archive requires the output to be a zip file");
+        exit(1);
+    }
+
+    if args.git_output && output_type != FileType::Directory {
+        eprintln!("--git-output requires the output to be a directory");
+        exit(1);
+    }
+
+    if args.verify_round_trip && output_type != FileType::Directory {
+        eprintln!("--verify-round-trip requires the output to be a directory");
+        exit(1);
+    }
+
+    match output_type {
+        FileType::Directory if args.git_output && output_path.exists() => {
+            if !output_path.join(mirror::GIT_DIRECTORY).is_dir() {
+                eprintln!("--git-output requires the output directory to be a git repository");
+                exit(1);
+            }
+            // The files are in version control, so partial output is not removed on expiry
+        }
+        FileType::Directory => {
+            if output_path.exists() {
+                if output_path.is_file() {
+                    eprintln!("Expected the output path to be an empty directory");
+                    exit(1);
+                } else if output_path.read_dir().unwrap().next().is_some() {
+                    eprintln!("The output directory must be empty");
+                    exit(1);
+                }
+                timeout::empty_on_expiry(output_path.to_path_buf());
+            } else {
+                fs::create_dir_all(output_path).unwrap();
+                timeout::remove_on_expiry(output_path.to_path_buf());
+            }
+        }
+        FileType::Zip if args.update_archive => {
+            if !output_path.is_file() {
+                eprintln!("--update-archive requires the output zip file to exist");
+                exit(1);
+            }
+            timeout::remove_on_expiry(output::partial_path(output_path));
+        }
+        FileType::Zip => {
+            if output_path.exists() {
+                eprintln!("The output zip file must not exist yet");
+                exit(1);
+            }
+            timeout::remove_on_expiry(output_path.to_path_buf());
+        }
+    }
+
+    let entries = walk_project(&args, recorded_inputs.as_ref(), &report);
+    let stats = args.stats.then(|| project_stats(&entries, &args));
+
+    let mut mapping = build_mapping(&entries, &args, &report);
+    if args.on_collision == OnCollision::Interactive {
+        resolve_collisions(&mut mapping, &args);
+    }
+
+    let snapshot = args.git_output.then(|| {
+        if let Some(source) = mapping.sources().find(|source| {
+            mapping
+                .name(source)
+                .is_some_and(|name| name.split('/').next() == Some(mirror::GIT_DIRECTORY))
+        }) {
+            report.emit(
+                Diagnostic::error(
+                    Code::GitDirectory,
+                    format!(
+                        "Refusing to write {:?} into the git directory of the output",
+                        mapping.name(source).unwrap()
+                    ),
+                )
+                .in_file(source),
+            );
+            exit(1);
+        }
+
+        mirror::snapshot(output_path).unwrap_or_else(|err| {
+            eprintln!("Failed to read the output directory: {err}");
+            exit(1);
+        })
+    });
+
+    if let Some(split) = args.split_output {
+        if output_type != FileType::Directory {
+            eprintln!("--split-output requires the output to be a directory");
+            exit(1);
+        }
+        split_project(&entries, &args, &mapping, split, &report);
+    } else {
+        // Create the archive only after walking, in case it is inside of the project
+        let output = match output_type {
+            FileType::Directory => Output::directory(output_path),
+            FileType::Zip => create_zip(output_path, &args),
+        };
+
+        let progress = progress_bar(&entries, &args);
+
+        if args.copy_only_referenced {
+            copy_referenced(&entries, &args, &mapping, &output, &report, &progress);
+        } else if args.bib_only {
+            copy_bibliographies(&entries, &args, &mapping, &output, &report, &progress);
+        } else {
+            let sources: Vec<_> = entries
+                .iter()
+                .map(|entry| source_path(entry.path(), &args))
+                .collect();
+            flatten_entries(entries, &args, &mapping, &output, &report, &progress);
+            if !args.no_unreferenced_warnings {
+                warn_unreferenced(&sources, &args, &mapping, &report);
+            }
+        }
+
+        if let Some(snapshot) = &snapshot {
+            write_git_files(snapshot, &args, &mapping, &output);
+        }
+
+        progress.finish_and_clear();
+        finish_output(output, &args);
+
+        if let Some(snapshot) = &snapshot {
+            print_git_changes(snapshot, &args);
+        }
+
+        if let Some(path) = &args.also_inline {
+            write_inlined(path, &args, &mapping);
+        }
+    }
+
+    if let Some(stats) = stats {
+        print_stats(stats, &report);
+    }
+
+    let engine = report.engine();
+    if !args.quiet && !matches!(engine, Engine::Pdflatex | Engine::Conflicting) {
+        eprintln!("The project needs the {} engine", engine.name());
+    }
+
+    if args.verify_round_trip {
+        let violations = verify::verify_round_trip(output_path, &args).unwrap_or_else(|err| {
+            eprintln!("Failed to read the output: {err}");
+            exit(1);
+        });
+
+        let count = violations.len();
+        for violation in violations {
+            report.emit(violation);
+        }
+        if count > 0 {
+            eprintln!("Failed because of {count} round trip violation(s)");
+            exit(1);
+        }
+    }
+
+    if let Some(hook) = &args.post_hook {
+        run_post_hook(hook, &args);
+    }
+
+    if let Some(ReportFormat::Json) = args.report {
+        write_report(&report, &args).unwrap_or_else(|err| {
+            eprintln!("Failed to write the report: {err}");
+            exit(1);
+        });
+    }
+
+    if let Some(path) = &args.manifest_csv {
+        File::create(path)
+            .and_then(|file| report.write_csv(file, args.manifest_hashes))
+            .unwrap_or_else(|err| {
+                eprintln!("Failed to write the CSV manifest: {err}");
+                exit(1);
+            });
+    }
+
+    exit_on_errors(&report);
+
+    if args.strict && report.warning_count() > 0 {
+        eprintln!(
+            "Failed because of {} warning(s) in strict mode",
+            report.warning_count()
+        );
+        exit(1);
+    }
+}
+
+/// Fail the run if errors were emitted which didn't stop it right away, like unbalanced
+/// draft markers, after all of them are reported.
+fn exit_on_errors(report: &Report) {
+    if report.error_count() > 0 {
+        eprintln!("Failed because of {} error(s)", report.error_count());
+        exit(1);
+    }
+}
+
+fn write_report(report: &Report, args: &Args) -> io::Result<()> {
+    if let Some(path) = &args.report_file {
+        report.write_json(File::create(path)?)
+    } else {
+        report.write_json(io::stdout().lock())
+    }
+}
+
+fn path_file_type(path: &Path) -> Result<FileType, FileTypeError> {
+    if let Some(extension) = path.extension() {
+        if extension.eq_ignore_ascii_case("zip") {
+            Ok(FileType::Zip)
+        } else {
+            Err(FileTypeError::InvalidExtension(extension.to_owned()))
+        }
+    } else {
+        Ok(FileType::Directory)
+    }
+}
+
+/// Run the hook command in the output directory, exiting if it fails.
+///
+/// For zip output, it runs in the directory containing the archive.
+fn run_post_hook(hook: &str, args: &Args) {
+    let dir = if args.out.is_dir() {
+        &*args.out
+    } else {
+        args.out.parent().unwrap_or(Path::new("."))
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C");
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c");
+        command
+    };
+    let output = command
+        .arg(hook)
+        .current_dir(dir)
+        .env("LATEX_FLATTEN_OUT", fs::canonicalize(&args.out).unwrap())
+        .stdin(Stdio::null())
+        // Keep stdout free for the report
+        .stdout(io::stderr())
+        .stderr(Stdio::piped())
+        .output()
+        .unwrap_or_else(|err| {
+            eprintln!("Failed to run the post hook: {err}");
+            exit(1);
+        });
+
+    if !output.status.success() {
+        eprintln!("The post hook failed with {}", output.status);
+        io::stderr().write_all(&output.stderr).unwrap();
+        exit(1);
+    }
+}
+
+/// Collect the files of the project, sorted by path.
+fn walk_project(
+    args: &Args,
+    recorded_inputs: Option<&RecordedInputs>,
+    report: &Report,
+) -> Vec<DirEntry> {
+    let root_docs = args.include_root_docs.then(|| {
+        args.files.root_docs().unwrap_or_else(|err| {
+            eprintln!("Config: {err}");
+            exit(1);
+        })
+    });
+    let is_root_doc = |e: &DirEntry| {
+        root_docs
+            .as_ref()
+            .is_some_and(|root_docs| root_docs.is_match(source_path(e.path(), args)))
+    };
+
+    let entries: Vec<_> = WalkDir::new(&args.path)
+        .follow_links(args.follow_symlinks)
+        .sort_by_file_name()
+        .into_iter()
+        // Never touch the version control data when working in place
+        .filter_entry(|e| {
+            !(args.in_place
+                && prune::VCS_DIRECTORIES
+                    .iter()
+                    .any(|vcs| e.file_name() == *vcs))
+        })
+        .filter_map(|e| e.ok())
+        .inspect(|_| timeout::check())
+        .filter(|e| e.path().is_file())
+        .filter(|e| {
+            recorded_inputs.is_none_or(|recorded| {
+                recorded.contains(e.path().strip_prefix(&args.path).unwrap())
+            }) || is_root_doc(e)
+        })
+        .inspect(|e| {
+            if !args.follow_symlinks {
+                check_inside_root(e.path(), args, report);
+            }
+        })
+        .collect();
+
+    if args.include.is_empty() {
+        entries
+    } else {
+        filter_included(entries, root_docs.as_ref(), args)
+    }
+}
+
+/// Restrict the files to the ones matching an `--include` glob or the `root_docs`,
+/// plus their transitive dependencies with `--follow-deps`.
+fn filter_included(
+    entries: Vec<DirEntry>,
+    root_docs: Option<&GlobSet>,
+    args: &Args,
+) -> Vec<DirEntry> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in &args.include {
+        builder.add(glob.clone());
+    }
+    let globs = builder.build().unwrap_or_else(|err| {
+        eprintln!("Include: {err}");
+        exit(1);
+    });
+
+    let sources: Vec<_> = entries
+        .iter()
+        .map(|e| source_path(e.path(), args))
+        .collect();
+    let mut included: BTreeSet<_> = sources
+        .iter()
+        .filter(|source| {
+            globs.is_match(source) || root_docs.is_some_and(|root_docs| root_docs.is_match(source))
+        })
+        .cloned()
+        .collect();
+
+    if args.follow_deps {
+        // Only the sources matter for resolving references, not the output names
+        let mut mapping = Mapping::default();
+        for source in &sources {
+            mapping.insert(source.clone(), source.clone());
+        }
+
+        let roots: Vec<_> = included.iter().cloned().collect();
+        included.extend(deps::referenced_files(&roots, args, &mapping));
+    }
+
+    entries
+        .into_iter()
+        .zip(sources)
+        .filter(|(_, source)| included.contains(source))
+        .map(|(entry, _)| entry)
+        .collect()
+}
+
+/// Exit if the file lies outside the project root, e.g. through a symbolic link.
+fn check_inside_root(path: &Path, args: &Args, report: &Report) {
+    let inside = fs::canonicalize(&args.path)
+        .and_then(|root| Ok(fs::canonicalize(path)?.starts_with(root)))
+        .unwrap_or(false);
+
+    if !inside {
+        report.emit(
+            Diagnostic::error(
+                Code::LinkOutsideRoot,
+                format!(
+                    "Lies outside the project root {}, refusing to copy it",
+                    args.path.display()
+                ),
+            )
+            .in_file(source_path(path, args)),
+        );
+        exit(1);
+    }
+}
+
+/// Rewrite the references of the LaTeX files of an already flat directory in place,
+/// assuming the files are named like `chapters__intro.tex` for `chapters/intro.tex`.
+fn rewrite_flat_project(entries: &[DirEntry], args: &Args, report: &Report) {
+    if fs::canonicalize(&args.path).ok() != fs::canonicalize(&args.out).ok() {
+        eprintln!("--in-place requires the output path to be the same as the input path");
+        exit(1);
+    }
+
+    if !args.in_place_force && !prune::is_version_controlled(&args.path) {
+        eprintln!(
+            "The project is not under version control, so rewritten files can't be restored. \
+            Pass --in-place-force to rewrite it anyway"
+        );
+        exit(1);
+    }
+
+    let mapping = flat_mapping(entries, args);
+
+    for entry in entries {
+        timeout::check();
+        let name = source_path(entry.path(), args);
+        if !is_tex(entry.path(), args) {
+            continue;
+        }
+        let Ok(content) = fs::read_to_string(entry.path()) else {
+            report.emit(
+                Diagnostic::warning(Code::InvalidUtf8, "Not valid UTF-8".to_string())
+                    .in_file(&name),
+            );
+            continue;
+        };
+
+        // Relative references resolve from the directory the file was flattened from
+        let source = mapping
+            .sources()
+            .find(|source| mapping.name(source) == Some(name.as_str()))
+            .unwrap_or(&name);
+        let rewritten = rewrite_content(Path::new(source), &content, args, &mapping, report);
+
+        if rewritten != content {
+            fs::write(entry.path(), rewritten).unwrap_or_else(|err| {
+                eprintln!("Failed to write {name}: {err}");
+                exit(1);
+            });
+            println!("Rewrote {name}");
+        }
+    }
+}
+
+/// The mapping of an already flat directory, from the paths the files were flattened from.
+///
+/// The files at the root are unflattened, e.g. `chapters__intro.tex` to `chapters/intro.tex`.
+/// The files in subdirectories were not flattened, so they keep their path.
+fn flat_mapping(entries: &[DirEntry], args: &Args) -> Mapping {
+    let mut mapping = Mapping::default();
+    mapping.set_flatten_case(args.flatten_case);
+
+    for entry in entries {
+        let name = source_path(entry.path(), args);
+        let source = if name.contains('/') {
+            name.clone()
+        } else {
+            name.replace("__", "/")
+        };
+        mapping.insert(source, name);
+    }
+
+    mapping
+}
+
+/// Delete the files of the project which are not referenced from the main documents.
+fn prune_project(entries: &[DirEntry], args: &Args, report: &Report) {
+    if fs::canonicalize(&args.path).ok() != fs::canonicalize(&args.out).ok() {
+        eprintln!("--in-place requires the output path to be the same as the input path");
+        exit(1);
+    }
+
+    if !args.in_place_force && !prune::is_version_controlled(&args.path) {
+        eprintln!(
+            "The project is not under version control, so deleted files can't be restored. \
+            Pass --in-place-force to prune it anyway"
+        );
+        exit(1);
+    }
+
+    let mapping = build_mapping(entries, args, report);
+    let referenced = referenced_from_main(args, &mapping, report);
+    let sources = entries.iter().map(|e| source_path(e.path(), args));
+    let unreferenced = prune::unreferenced_files(sources, &referenced);
+
+    if !unreferenced.is_empty() && !args.yes {
+        confirm_deletion(&unreferenced);
+    }
+
+    prune::prune_in_place(&args.path, &unreferenced).unwrap_or_else(|err| {
+        eprintln!("Failed to delete file: {err}");
+        exit(1);
+    });
+
+    for source in unreferenced {
+        println!("Deleted {source}");
+    }
+}
+
+/// List the files of the project which are not referenced from the main documents,
+/// exiting with an error if there are any.
+fn check_unreferenced(entries: &[DirEntry], args: &Args, report: &Report) {
+    let mapping = build_mapping(entries, args, report);
+    let referenced = referenced_from_main(args, &mapping, report);
+    let sources = entries.iter().map(|e| source_path(e.path(), args));
+    let unreferenced = prune::unreferenced_files(sources, &referenced);
+
+    for source in &unreferenced {
+        report.emit(
+            Diagnostic::error(
+                Code::Unreferenced,
+                "Not referenced from the main document".to_string(),
+            )
+            .in_file(source),
+        );
+    }
+
+    if !unreferenced.is_empty() {
+        eprintln!(
+            "Failed because of {} unreferenced file(s)",
+            unreferenced.len()
+        );
+        exit(1);
+    }
+}
+
+/// Warn about the copied files which are not referenced from the main documents.
+///
+/// A file is referenced if a referenced file has the same output name,
+/// like a duplicate of it. Nothing is reported if there is no main document.
+fn warn_unreferenced(sources: &[String], args: &Args, mapping: &Mapping, report: &Report) {
+    let roots = deps::main_documents(args, mapping);
+    if roots.is_empty() {
+        return;
+    }
+
+    let referenced: HashSet<_> = deps::referenced_files(&roots, args, mapping)
+        .iter()
+        .filter_map(|source| mapping.name(source))
+        .map(str::to_string)
+        .collect();
+    let unreferenced = sources.iter().filter(|source| {
+        prune::is_prunable(source)
+            && !mapping.is_duplicate(source)
+            && !mapping.is_oversized(source)
+            && !mapping
+                .name(source)
+                .is_some_and(|name| referenced.contains(name))
+    });
+
+    for source in unreferenced {
+        report.emit(
+            Diagnostic::warning(
+                Code::Unreferenced,
+                "Copied, but not referenced from the main document. \
+                 Use --copy-only-referenced to leave it out"
+                    .to_string(),
+            )
+            .in_file(source),
+        );
+    }
+}
+
+/// Copy the files needed by the main documents to the output, keeping their paths.
+///
+/// Like for pruning, files which include commands can't reference are always copied.
+fn copy_referenced(
+    entries: &[DirEntry],
+    args: &Args,
+    mapping: &Mapping,
+    output: &Output,
+    report: &Report,
+    progress: &ProgressBar,
+) {
+    let referenced = referenced_from_main(args, mapping, report);
+
+    for entry in entries {
+        timeout::check();
+        progress.inc(file_size(entry));
+        let source = source_path(entry.path(), args);
+
+        if !referenced.contains(&source) && prune::is_prunable(&source) {
+            continue;
+        }
+
+        if mapping.is_oversized(&source) {
+            report.emit(
+                Diagnostic::info(
+                    Code::OversizedFile,
+                    format!(
+                        "Skipped, {} bytes is larger than --max-file-size",
+                        file_size(entry)
+                    ),
+                )
+                .in_file(&source),
+            );
+            continue;
+        }
+
+        let sha256 = copy_file(entry.path(), &source, args, output);
+
+        report.add_file(FileEntry {
+            output: source.clone(),
+            source,
+            copied: true,
+            sha256,
+        });
+    }
+}
+
+/// Only copy the bibliographies and bibliography styles referenced from the main documents,
+/// with their flattened names.
+fn copy_bibliographies(
+    entries: &[DirEntry],
+    args: &Args,
+    mapping: &Mapping,
+    output: &Output,
+    report: &Report,
+    progress: &ProgressBar,
+) {
+    let referenced = referenced_from_main(args, mapping, report);
+    let bibliographies: Vec<_> = entries
+        .iter()
+        .filter(|entry| {
+            let source = source_path(entry.path(), args);
+            referenced.contains(&source) && is_bibliography(Path::new(&source))
+        })
+        .cloned()
+        .collect();
+
+    // Collisions of the other files don't matter
+    if check_collisions(&build_mapping(&bibliographies, args, report), report) {
+        exit(1);
+    }
+
+    for entry in entries {
+        progress.inc(file_size(entry));
+    }
+    for entry in bibliographies {
+        timeout::check();
+        let source = source_path(entry.path(), args);
+        if mapping.is_duplicate(&source) {
+            continue;
+        }
+        let name = mapping.name(&source).unwrap();
+
+        let sha256 = copy_file(entry.path(), name, args, output);
+
+        report.add_file(FileEntry {
+            source,
+            output: name.to_string(),
+            copied: true,
+            sha256,
+        });
+    }
+}
+
+/// Collect the files which are transitively referenced from the main documents.
+fn referenced_from_main(args: &Args, mapping: &Mapping, report: &Report) -> BTreeSet<String> {
+    let roots = deps::main_documents(args, mapping);
+
+    if roots.is_empty() {
+        report.emit(Diagnostic::error(
+            Code::NoMainDocument,
+            "No main document found, specify it with --main".to_string(),
+        ));
+        exit(1);
+    } else if let Some(missing) = roots.iter().find(|root| mapping.name(root).is_none()) {
+        report.emit(missing_main_document(missing));
+        exit(1);
+    }
+
+    deps::referenced_files(&roots, args, mapping)
+}
+
+/// The error for a main document given with `--main` which doesn't exist.
+fn missing_main_document(main: &str) -> Diagnostic {
+    Diagnostic::error(
+        Code::MissingMainDocument,
+        "The main document does not exist".to_string(),
+    )
+    .in_file(main)
+}
+
+/// List the files to be deleted and ask for confirmation, exiting unless it is given.
+///
+/// Without a terminal to ask on, `--yes` is required.
+fn confirm_deletion(files: &[String]) {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        eprintln!(
+            "--in-place would delete {} file(s). Pass --yes to confirm when not running interactively",
+            files.len()
+        );
+        exit(1);
+    }
+
+    eprintln!("The following files will be deleted:");
+    for file in files {
+        eprintln!("  {file}");
+    }
+
+    let confirmed = prompt::confirm(
+        &format!("Delete {} file(s)?", files.len()),
+        stdin.lock(),
+        io::stderr(),
+    )
+    .unwrap_or_else(|err| {
+        eprintln!("Failed to read the confirmation: {err}");
+        exit(1);
+    });
+    if !confirmed {
+        eprintln!("Aborted, no files were deleted");
+        exit(1);
+    }
+}
+
+/// Register the existing files outside of the project root which are referenced
+/// from the project, or transitively from the registered external files.
+fn insert_external_refs(entries: &[DirEntry], args: &Args, mapping: &mut Mapping) {
+    let mut pending: Vec<_> = entries
+        .iter()
+        .map(|entry| source_path(entry.path(), args))
+        .collect();
+
+    while let Some(source) = pending.pop() {
+        if !is_tex(Path::new(&source), args) {
+            continue;
+        }
+        let Ok(content) = fs::read_to_string(args.path.join(&source)) else {
+            continue;
+        };
+
+        let mut external = Vec::new();
+        let mut scanner = LineScanner::new(Path::new(&source));
+        let mut scope = Scope::for_file(&source, mapping);
+        for (_, line, _) in join_continued_commands(&content, args)
+            .into_iter()
+            .filter(|(_, line, _)| scanner.scan(line) == LineKind::Latex)
+        {
+            scope.define(line, &source, mapping);
+            trace_imports(line, args, mapping, &scope, |import| {
+                let kind = args.commands.kind(&import.command).unwrap();
+                for reference in import.outside_root {
+                    // Like LaTeX, try the literal path first
+                    let existing = iter::once(reference.clone())
+                        .chain(
+                            kind.extensions()
+                                .iter()
+                                .map(|extension| format!("{reference}.{extension}")),
+                        )
+                        .find(|candidate| args.path.join(candidate).is_file());
+                    external.extend(existing);
+                }
+            });
+        }
+
+        for reference in external {
+            if mapping.resolve(&reference, &[]).is_none() {
+                let name = args.flatten_case.apply(mapping::external_name(&reference));
+                mapping.insert_external(&reference, name);
+                pending.push(mapping.resolve(&reference, &[]).unwrap().to_string());
+            }
+        }
+    }
+}
+
+/// Register the files without extension which are included by input-like commands,
+/// so that they are processed like LaTeX sources.
+fn insert_extensionless_inputs(args: &Args, mapping: &mut Mapping) {
+    let mut pending: Vec<_> = mapping
+        .sources()
+        .filter(|source| is_tex(Path::new(source), args) || mapping.is_processed_main(source))
+        .map(str::to_string)
+        .collect();
+
+    while let Some(source) = pending.pop() {
+        let Ok(content) = fs::read_to_string(args.path.join(&source)) else {
+            continue;
+        };
+
+        let mut included = Vec::new();
+        let mut scanner = LineScanner::new(Path::new(&source));
+        let mut scope = Scope::for_file(&source, mapping);
+        for (_, line, _) in join_continued_commands(&content, args)
+            .into_iter()
+            .filter(|(_, line, _)| scanner.scan(line) == LineKind::Latex)
+        {
+            scope.define(line, &source, mapping);
+            trace_imports(line, args, mapping, &scope, |import| {
+                if args.commands.kind(&import.command) == Some(CommandKind::Input) {
+                    included.extend(import.targets);
+                }
+            });
+        }
+
+        for target in included {
+            if Path::new(&target).extension().is_none() && !mapping.is_extensionless_input(&target)
+            {
+                mapping.insert_extensionless_input(target.clone());
+                pending.push(target);
+            }
+        }
+    }
+}
+
+fn create_zip(path: &Path, args: &Args) -> Output {
+    let compression = args.compression.unwrap_or_default();
+    if args.update_archive {
+        return Output::update_zip(path, compression).unwrap_or_else(|err| {
+            eprintln!("Failed to open the output zip file: {err}");
+            exit(1);
+        });
+    }
+
+    Output::zip(path, compression).unwrap_or_else(|err| {
+        eprintln!("Failed to create the output zip file: {err}");
+        exit(1);
+    })
+}
+
+/// Write the main document with its inputs merged into it to the given path.
+fn write_inlined(path: &Path, args: &Args, mapping: &Mapping) {
+    let roots = deps::main_documents(args, mapping);
+    let [main] = &roots[..] else {
+        eprintln!(
+            "--also-inline requires exactly one main document, found {}. Specify it with --main",
+            roots.len()
+        );
+        exit(1);
+    };
+
+    let manifest = match &args.merge_order {
+        Some(order) => fs::read_to_string(order)
+            .unwrap_or_else(|err| {
+                eprintln!("Failed to read the merge order {}: {err}", order.display());
+                exit(1);
+            })
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let inlined = inline::inline_document(main, args, mapping, &manifest);
+    fs::write(path, inlined).unwrap_or_else(|err| {
+        eprintln!("Failed to write the inlined document: {err}");
+        exit(1);
+    });
+}
+
+fn finish_output(output: Output, args: &Args) {
+    let summary = output.finish().unwrap_or_else(|err| {
+        eprintln!("Failed to write the output: {err}");
+        exit(1);
+    });
+
+    if let Some(summary) = summary.filter(|_| !args.quiet) {
+        eprintln!(
+            "Updated the archive: {} file(s) unchanged, {} rewritten, {} removed",
+            summary.unchanged, summary.rewritten, summary.removed
+        );
+    }
+}
+
+/// Write the `.gitattributes` and `.gitignore` of a `--git-output` directory,
+/// unless they are in the snapshot of the directory before the run.
+fn write_git_files(
+    snapshot: &BTreeMap<String, Vec<u8>>,
+    args: &Args,
+    mapping: &Mapping,
+    output: &Output,
+) {
+    let binary_extensions: BTreeSet<_> = mapping
+        .sources()
+        .filter(|source| !mapping.is_duplicate(source) && !is_processed(source, args, mapping))
+        .filter(|source| {
+            let mut start = Vec::new();
+            File::open(args.path.join(source))
+                .and_then(|file| file.take(8000).read_to_end(&mut start))
+                .is_ok_and(|_| mirror::is_binary(&start))
+        })
+        .filter_map(|source| {
+            let name = mapping.name(source)?;
+            Some(Path::new(name).extension()?.to_str()?.to_string())
+        })
+        .collect();
+
+    for (name, content) in [
+        (".gitattributes", mirror::gitattributes(&binary_extensions)),
+        (".gitignore", mirror::gitignore()),
+    ] {
+        if !snapshot.contains_key(name) {
+            output
+                .write(name, content.as_bytes())
+                .unwrap_or_else(|_| panic!("Failed to create new file {name:?}"));
+        }
+    }
+}
+
+/// Print the files of the `--git-output` directory which were added or changed by the run.
+fn print_git_changes(snapshot: &BTreeMap<String, Vec<u8>>, args: &Args) {
+    let after = mirror::snapshot(&args.out).unwrap_or_else(|err| {
+        eprintln!("Failed to read the output directory: {err}");
+        exit(1);
+    });
+    let changes = mirror::changes(snapshot, &after);
+
+    if !args.quiet {
+        for name in &changes.added {
+            eprintln!("Added {name}");
+        }
+        for name in &changes.changed {
+            eprintln!("Changed {name}");
+        }
+        eprintln!(
+            "{} file(s) added, {} changed",
+            changes.added.len(),
+            changes.changed.len()
+        );
+    }
+}
+
+/// The files and bytes per top-level directory of the walked files.
+fn project_stats(entries: &[DirEntry], args: &Args) -> Vec<DirectoryStats> {
+    stats::directory_stats(
+        entries
+            .iter()
+            .map(|entry| (source_path(entry.path(), args), file_size(entry))),
+    )
+}
+
+/// Print the statistics to stderr and add them to the report.
+fn print_stats(stats: Vec<DirectoryStats>, report: &Report) {
+    eprint!("{}", stats::render_stats(&stats));
+    report.set_stats(stats);
+}
+
+/// Print the flattened name of each file, as a list or with `--tree` as a tree.
+fn print_dry_run(entries: &[DirEntry], args: &Args, mapping: &Mapping) {
+    let files: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            let source = source_path(entry.path(), args);
+            let name = mapping.name(&source).unwrap().to_string();
+            (source, name)
+        })
+        .collect();
+
+    if args.tree {
+        let color =
+            !args.no_color && env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal();
+        let root = fs::canonicalize(&args.path)
+            .ok()
+            .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| args.path.display().to_string());
+        print!(
+            "{}",
+            tree::render_tree(&root, &files, color, tree::MAX_FILES)
+        );
+    } else {
+        for (source, name) in files {
+            println!("{source} -> {name}");
+        }
+    }
+}
+
+/// Write the flattened files of the mapping to the output, failing on collisions.
+fn flatten_entries(
+    entries: Vec<DirEntry>,
+    args: &Args,
+    mapping: &Mapping,
+    output: &Output,
+    report: &Report,
+    progress: &ProgressBar,
+) {
+    if check_collisions(mapping, report) {
+        exit(1);
+    }
+
+    entries
+        .into_iter()
+        .for_each(|e| process_entry(e, args, mapping, output, report, progress));
+
+    for source in mapping.externals() {
+        let name = mapping.name(source).unwrap();
+        let sha256 = write_content(
+            &args.path.join(source),
+            Path::new(source),
+            name,
+            args,
+            mapping,
+            output,
+            report,
+        );
+
+        report.add_file(FileEntry {
+            source: source.to_string(),
+            output: name.to_string(),
+            copied: true,
+            sha256,
+        });
+    }
+}
+
+/// Flatten each main document into its own output in the output directory,
+/// with the files it references and those include commands can't reference.
+///
+/// Each document gets its own mapping, so that only its own files can collide.
+fn split_project(
+    entries: &[DirEntry],
+    args: &Args,
+    mapping: &Mapping,
+    split: SplitOutput,
+    report: &Report,
+) {
+    let roots = deps::main_documents(args, mapping);
+    if roots.is_empty() {
+        report.emit(Diagnostic::error(
+            Code::NoMainDocument,
+            "No main document found to split the output by".to_string(),
+        ));
+        exit(1);
+    }
+
+    for root in roots {
+        let referenced = deps::referenced_files(slice::from_ref(&root), args, mapping);
+        let document_entries: Vec<_> = entries
+            .iter()
+            .filter(|entry| {
+                let source = source_path(entry.path(), args);
+                referenced.contains(&source) || !prune::is_prunable(&source)
+            })
+            .cloned()
+            .collect();
+        let mut document_mapping = build_mapping(&document_entries, args, report);
+        if args.on_collision == OnCollision::Interactive {
+            resolve_collisions(&mut document_mapping, args);
+        }
+
+        let name = mapping.name(&root).unwrap();
+        let document = Path::new(name).file_stem().unwrap().to_string_lossy();
+        let output = match split {
+            SplitOutput::Dir => {
+                let path = args.out.join(&*document);
+                fs::create_dir(&path).unwrap_or_else(|err| {
+                    eprintln!(
+                        "Failed to create the output directory {}: {err}",
+                        path.display()
+                    );
+                    exit(1);
+                });
+                Output::directory(&path)
+            }
+            SplitOutput::Zip => create_zip(&args.out.join(format!("{document}.zip")), args),
+        };
+
+        let progress = progress_bar(&document_entries, args);
+        flatten_entries(
+            document_entries,
+            args,
+            &document_mapping,
+            &output,
+            report,
+            &progress,
+        );
+        progress.finish_and_clear();
+        finish_output(output, args);
+    }
+}
+
+/// Determine the output names of all files.
+fn build_mapping(entries: &[DirEntry], args: &Args, report: &Report) -> Mapping {
+    let mut mapping = if args.normalize_unicode {
+        Mapping::with_unicode_normalization()
+    } else {
+        Mapping::default()
+    };
+    mapping.set_flatten_case(args.flatten_case);
+    mapping.set_keep_unresolved(has_naming_scheme(args));
+    // The source of the first asset with the given content
+    let mut digests = HashMap::new();
+    // The source of the first asset with the given name and content
+    let mut named_digests = HashMap::new();
+    let sources: HashSet<_> = entries
+        .iter()
+        .map(|entry| source_path(entry.path(), args))
+        .collect();
+    let canonical_sources = if args.follow_symlinks {
+        canonical_sources(entries, args)
+    } else {
+        HashMap::new()
+    };
+    // The sources reached through symbolic links, with the source of the same file
+    let mut linked = Vec::new();
+
+    for entry in entries {
+        timeout::check();
+        let source = source_path(entry.path(), args);
+        // An index file keeps its name if a sibling would get the collapsed one
+        let collapse = args.collapse_index
+            && is_tex(entry.path(), args)
+            && collapsed_index(&source).is_some_and(|collapsed| !sources.contains(&collapsed));
+        let mut name = flatten_path(entry.path(), collapse, args)
+            .into_os_string()
+            .into_string()
+            .unwrap();
+        if args.normalize_unicode {
+            name = name.nfc().collect();
+        }
+        if let Some(asset_dir) = args.asset_dir.as_ref().filter(|_| is_asset(&source, args)) {
+            name = format!("{asset_dir}/{name}");
+        }
+
+        if let Some(canonical) = fs::canonicalize(entry.path())
+            .ok()
+            .and_then(|path| canonical_sources.get(&path))
+            .filter(|canonical| **canonical != source)
+        {
+            linked.push((source, canonical.clone()));
+            continue;
+        }
+
+        // The digest is shared by both kinds of deduplication, so each file is only read once
+        let digest = ((args.merge_assets_by_basename || args.dedupe_assets)
+            && !is_tex(entry.path(), args))
+        .then(|| read_digest(entry.path(), args));
+
+        if let Some(digest) = digest.as_ref().filter(|_| args.merge_assets_by_basename) {
+            let key = (name.clone(), digest.clone());
+
+            if let Some(canonical) = named_digests.get(&key) {
+                mapping.insert_duplicate(source, String::clone(canonical));
+                continue;
+            }
+
+            named_digests.insert(key, source.clone());
+        }
+
+        if let Some(digest) = digest.filter(|_| args.dedupe_assets) {
+            if let Some(canonical) = digests.get(&digest) {
+                mapping.insert_duplicate(source, String::clone(canonical));
+                continue;
+            }
+
+            digests.insert(digest, source.clone());
+        }
+
+        if args
+            .max_file_size
+            .is_some_and(|max_file_size| file_size(entry) > max_file_size)
+        {
+            mapping.insert_oversized(source.clone());
+        }
+
+        mapping.insert(source, name);
+    }
+    for (source, canonical) in linked {
+        mapping.insert_duplicate(source, canonical);
+    }
+
+    if let Some(name) = &args.main_out_name {
+        let roots = deps::main_documents(args, &mapping);
+        let [main] = &roots[..] else {
+            eprintln!(
+                "--main-out-name requires exactly one main document, found {}. \
+                 Specify it with --main",
+                roots.len()
+            );
+            exit(1);
+        };
+
+        if mapping.name(main).is_none() {
+            report.emit(missing_main_document(main));
+            exit(1);
+        }
+        mapping.insert(main.clone(), name.clone());
+    }
+
+    if args.extract_preamble {
+        let roots = deps::main_documents(args, &mapping);
+        let [main] = &roots[..] else {
+            eprintln!(
+                "--extract-preamble requires exactly one main document, found {}. \
+                 Specify it with --main",
+                roots.len()
+            );
+            exit(1);
+        };
+
+        if let Some(source) = mapping.sources().find(|source| {
+            mapping
+                .name(source)
+                .is_some_and(|name| name.eq_ignore_ascii_case(PREAMBLE_NAME))
+        }) {
+            eprintln!("--extract-preamble: {source:?} is already flattened to {PREAMBLE_NAME:?}");
+            exit(1);
+        }
+        mapping.set_preamble_source(main.clone());
+    }
+
+    if args.include_external_refs {
+        insert_external_refs(entries, args, &mut mapping);
+    }
+    let package_macros = deps::package_macros(args, &mapping);
+    mapping.set_package_macros(package_macros);
+    if args.main.is_some() || !args.files.main.is_empty() {
+        for main in deps::main_documents(args, &mapping) {
+            if mapping.name(&main).is_some() && !is_tex(Path::new(&main), args) {
+                mapping.insert_processed_main(main);
+            }
+        }
+    }
+    insert_extensionless_inputs(args, &mut mapping);
+
+    let graphics_paths = deps::graphics_paths(args, &mapping);
+    mapping.set_graphics_paths(graphics_paths);
+    mapping
+}
+
+/// The source of each file by its canonical path, for files reached through symbolic links.
+///
+/// The path without symbolic links is preferred, otherwise the first path is taken.
+fn canonical_sources(entries: &[DirEntry], args: &Args) -> HashMap<PathBuf, String> {
+    let root = fs::canonicalize(&args.path).unwrap_or_else(|_| args.path.to_path_buf());
+    let mut sources: HashMap<PathBuf, String> = HashMap::new();
+
+    for entry in entries {
+        let Ok(canonical) = fs::canonicalize(entry.path()) else {
+            continue;
+        };
+        let source = source_path(entry.path(), args);
+        let direct = root.join(&source) == canonical;
+
+        match sources.get_mut(&canonical) {
+            Some(first) if direct => *first = source,
+            Some(_) => {}
+            None => {
+                sources.insert(canonical, source);
+            }
+        }
+    }
+
+    sources
+}
+
+fn parse_main_out_name(name: &str) -> Result<String, TemplateError> {
+    template::check_name(name)?;
+    Ok(name.to_string())
+}
+
+fn parse_asset_dir(name: &str) -> Result<String, String> {
+    template::check_name(name).map_err(|err| err.to_string())?;
+    if name == "." || name == ".." {
+        return Err(format!("{name:?} is not a directory name"));
+    }
+    Ok(name.to_string())
+}
+
+/// Determine if the source is an asset, which is written to the `--asset-dir`.
+///
+/// These are the files which are neither processed nor bibliographies,
+/// as BibTeX looks up the latter next to the document.
+fn is_asset(source: &str, args: &Args) -> bool {
+    let path = Path::new(source);
+    !is_tex(path, args) && !is_bibliography(path)
+}
+
+/// Determine if the file is a bibliography or a bibliography style, by extension.
+fn is_bibliography(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        BIBLIOGRAPHY_EXTENSIONS
+            .iter()
+            .chain(&BIBLIOGRAPHY_STYLE_EXTENSIONS)
+            .any(|bibliography| ext.eq_ignore_ascii_case(bibliography))
+    })
+}
+
+/// Report files with conflicting output names.
+///
+/// Exact collisions would overwrite files, so they are errors and the result is true;
+/// collisions which only differ in case are warned about.
+fn check_collisions(mapping: &Mapping, report: &Report) -> bool {
+    let mut has_exact_collision = false;
+
+    for collision in mapping.collisions() {
+        let (first, second) = &collision.sources;
+        let (first_name, second_name) = &collision.names;
+
+        match collision.kind {
+            CollisionKind::Exact => {
+                has_exact_collision = true;
+                report.emit(Diagnostic::error(
+                    Code::ExactCollision,
+                    format!("{first:?} and {second:?} are both flattened to {first_name:?}"),
+                ));
+            }
+            CollisionKind::Case => report.emit(Diagnostic::warning(
+                Code::CaseCollision,
+                format!(
+                    "{first:?} and {second:?} are flattened to {first_name:?} and {second_name:?}, \
+                    which collide on case-insensitive file systems"
+                ),
+            )),
+        }
+    }
+
+    has_exact_collision
+}
+
+/// Ask for a new name for the second file of each collision, until there are none left.
+///
+/// The names are changed in the mapping, so both the written files and the references use them.
+/// Without a terminal to ask on, this fails.
+fn resolve_collisions(mapping: &mut Mapping, args: &Args) {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        eprintln!("--on-collision interactive requires a terminal to ask for the names on");
+        exit(1);
+    }
+
+    while let Some(collision) = mapping.collisions().into_iter().next() {
+        let (first, second) = &collision.sources;
+        let (first_name, second_name) = &collision.names;
+        match collision.kind {
+            CollisionKind::Exact => {
+                eprintln!("{first:?} and {second:?} are both flattened to {first_name:?}")
+            }
+            CollisionKind::Case => eprintln!(
+                "{first:?} and {second:?} are flattened to {first_name:?} and {second_name:?}, \
+                which collide on case-insensitive file systems"
+            ),
+        }
+
+        let digest = read_digest(&args.path.join(second), args);
+        let suggested = hashed_name(second_name, &digest);
+        let name = loop {
+            let name = prompt::ask(
+                &format!("New name for {second:?}?"),
+                &suggested,
+                stdin.lock(),
+                io::stderr(),
+            )
+            .unwrap_or_else(|err| {
+                eprintln!("Failed to read the name: {err}");
+                exit(1);
+            });
+
+            if !matches!(
+                Path::new(&name).components().collect::<Vec<_>>()[..],
+                [Component::Normal(_)]
+            ) {
+                eprintln!("{name:?} is not a file name");
+            } else if mapping.sources().any(|source| {
+                source != second
+                    && mapping
+                        .name(source)
+                        .is_some_and(|taken| taken.eq_ignore_ascii_case(&name))
+            }) {
+                eprintln!("{name:?} is already taken");
+            } else {
+                break name;
+            }
+        };
+
+        mapping.insert(second.clone(), name);
+    }
+}
+
+/// The name with a short hash of the file content before its extension,
+/// e.g. `intro-1a2b3c4d.tex` for `intro.tex`.
+fn hashed_name(name: &str, digest: &[u8]) -> String {
+    let hash = to_hex(&digest[..4]);
+
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{stem}-{hash}.{extension}"),
+        _ => format!("{name}-{hash}"),
+    }
+}
+
+/// Report the problems that would occur when flattening the project, without writing anything.
+///
+/// Returns true if there are exact collisions.
+fn check_project(entries: &[DirEntry], args: &Args, report: &Report) -> bool {
+    let mapping = build_mapping(entries, args, report);
+    let has_exact_collision = check_collisions(&mapping, report);
+    // The first definition of each label
+    let mut labels = HashMap::new();
+
+    for entry in entries
+        .iter()
+        .filter(|e| is_processed(&source_path(e.path(), args), args, &mapping))
+    {
+        let relative_path = entry.path().strip_prefix(&args.path).unwrap();
+        let Ok(content) = fs::read_to_string(entry.path()) else {
+            report.emit(
+                Diagnostic::warning(Code::InvalidUtf8, "Not valid UTF-8".to_string())
+                    .in_file(relative_path.display()),
+            );
+            continue;
+        };
+        rewrite_content(relative_path, &content, args, &mapping, report);
+
+        let mut scanner = LineScanner::new(relative_path);
+        for (index, line) in content.lines().enumerate() {
+            if scanner.scan(line) != LineKind::Latex {
+                continue;
+            }
+
+            let location = format!("{}:{}", relative_path.display(), index + 1);
+            for label in labels::find_labels(line) {
+                if let Some(first) = labels.get(label) {
+                    report.emit(
+                        Diagnostic::warning(
+                            Code::DuplicateLabel,
+                            format!("The label {label:?} is already defined at {first}"),
+                        )
+                        .at(relative_path.display(), index + 1),
+                    );
+                } else {
+                    labels.insert(label.to_string(), location.clone());
+                }
+            }
+        }
+    }
+
+    has_exact_collision
+}
+
+/// A progress bar over the bytes of the files, with throughput and remaining time.
+///
+/// It is hidden unless `--progress` is given, and when stderr is not a terminal.
+fn progress_bar(entries: &[DirEntry], args: &Args) -> ProgressBar {
+    if !args.progress || args.quiet {
+        return ProgressBar::hidden();
+    }
+
+    let total = entries.iter().map(file_size).sum();
+    ProgressBar::new(total).with_style(
+        ProgressStyle::with_template(
+            "{wide_bar} {binary_bytes}/{binary_total_bytes} \
+             ({binary_bytes_per_sec}, {eta} left)",
+        )
+        .unwrap(),
+    )
+}
+
+fn file_size(entry: &DirEntry) -> u64 {
+    entry.metadata().map_or(0, |metadata| metadata.len())
+}
+
+/// Compute the SHA-256 digest of the file content.
+fn file_digest(path: &Path) -> io::Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Compute the SHA-256 digest of the file content,
+/// stopping the run with an error if the file can't be read.
+fn read_digest(path: &Path, args: &Args) -> Vec<u8> {
+    file_digest(path).unwrap_or_else(|err| {
+        let diagnostic = Diagnostic::error(Code::UnreadableFile, format!("Failed to read: {err}"))
+            .in_file(source_path(path, args));
+        diagnostic::print(&diagnostic, false);
+        exit(1);
+    })
+}
+
+/// A reader computing the SHA-256 digest of the content read through it.
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// Only rewinding is supported, which starts the digest over.
+impl<R: Seek> Seek for HashingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        if pos != SeekFrom::Start(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Only rewinding a hashed file is supported",
+            ));
+        }
+        self.hasher = Sha256::new();
+        self.inner.seek(pos)
+    }
+}
+
+/// The hexadecimal SHA-256 digest of the written content, if `--manifest-hashes` is given.
+fn content_digest(content: &[u8], args: &Args) -> Option<String> {
+    args.manifest_hashes
+        .then(|| to_hex(&Sha256::digest(content)))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn process_entry(
+    entry: DirEntry,
+    args: &Args,
+    mapping: &Mapping,
+    output: &Output,
+    report: &Report,
+    progress: &ProgressBar,
+) {
+    timeout::check();
+    progress.inc(file_size(&entry));
+    let source = source_path(entry.path(), args);
+
+    if mapping.is_oversized(&source) {
+        report.emit(
+            Diagnostic::info(
+                Code::OversizedFile,
+                format!(
+                    "Skipped, {} bytes is larger than --max-file-size",
+                    file_size(&entry)
+                ),
+            )
+            .in_file(&source),
+        );
+        return;
+    }
+
+    let name = mapping.name(&source).unwrap().to_string();
+    let copied = !mapping.is_duplicate(&source);
+
+    if !copied {
+        report.add_file(FileEntry {
+            source,
+            output: name,
+            copied,
+            sha256: None,
+        });
+        return;
+    }
+
+    // Never write outside of the output directory
+    let in_output = match Path::new(&name).components().collect::<Vec<_>>()[..] {
+        [Component::Normal(_)] => true,
+        [Component::Normal(dir), Component::Normal(_)] => args
+            .asset_dir
+            .as_deref()
+            .is_some_and(|asset_dir| dir == asset_dir),
+        _ => false,
+    };
+    if !in_output {
+        report.emit(
+            Diagnostic::error(
+                Code::OutsideOutput,
+                format!("Refusing to write {name:?} outside of the output directory"),
+            )
+            .in_file(&source),
+        );
+        exit(1);
+    }
+
+    let relative_path = entry.path().strip_prefix(&args.path).unwrap();
+    let sha256 = write_content(
+        entry.path(),
+        relative_path,
+        &name,
+        args,
+        mapping,
+        output,
+        report,
+    );
+
+    report.add_file(FileEntry {
+        source,
+        output: name,
+        copied,
+        sha256,
+    });
+}
+
+/// The path relative to the project root, with `/` separators.
+fn source_path(path: &Path, args: &Args) -> String {
+    let root_components = args.path.components().count();
+    let components: Vec<_> = path
+        .components()
+        .skip(root_components)
+        .map(|component| component.as_os_str().to_str().unwrap())
+        .collect();
+    components.join("/")
+}
+
+/// The flattened name of the file, named after its directory if `collapse` is set.
+fn flatten_path(path: &Path, collapse: bool, args: &Args) -> PathBuf {
+    let name = if args.merge_assets_by_basename && !is_tex(path, args) {
+        path.file_name().unwrap().to_string_lossy().into_owned()
+    } else {
+        let mut source = name_source(path, args);
+        if collapse {
+            source = collapsed_index(&source).unwrap_or(source);
+        }
+
+        if let Some(template) = &args.rename_template {
+            let hash = if template.uses_hash() {
+                let digest = read_digest(path, args);
+                to_hex(&digest[..4])
+            } else {
+                String::new()
+            };
+
+            template.expand(&source, &hash).unwrap_or_else(|err| {
+                eprintln!("Rename template: {err}");
+                exit(1);
+            })
+        } else {
+            source.replace('/', "__")
+        }
+    };
+
+    let namespace = if args.namespace_by_type {
+        type_namespace(path, args)
+    } else {
+        ""
+    };
+    args.flatten_case.apply(format!("{namespace}{name}")).into()
+}
+
+/// Determine if the flattened names are not just the source paths with `/` replaced,
+/// so they can't be derived for references which don't resolve to a file.
+fn has_naming_scheme(args: &Args) -> bool {
+    args.namespace_by_type
+        || args.asset_dir.is_some()
+        || args.rename_template.is_some()
+        || args.relative_to.is_some()
+        || args.merge_assets_by_basename
+}
+
+/// The prefix of the flattened name for the kind of file, with `--namespace-by-type`.
+///
+/// LaTeX files get `tex__`, graphics `fig__` and bibliographies and their styles `bib__`.
+/// Other files, like data or documentation, get no prefix.
+fn type_namespace(path: &Path, args: &Args) -> &'static str {
+    let has_extension = |extensions: &[&str]| {
+        path.extension().is_some_and(|ext| {
+            extensions
+                .iter()
+                .any(|known| ext.eq_ignore_ascii_case(known))
+        })
+    };
+
+    if is_tex(path, args) {
+        "tex__"
+    } else if has_extension(&GRAPHICS_EXTENSIONS) {
+        "fig__"
+    } else if is_bibliography(path) {
+        "bib__"
+    } else {
+        ""
+    }
+}
+
+/// The path of an index file named after its directory,
+/// e.g. `chapters/intro.tex` for `chapters/intro/index.tex`.
+fn collapsed_index(source: &str) -> Option<String> {
+    let (directory, file) = source.rsplit_once('/')?;
+    let extension = file.strip_prefix("index.")?;
+    Some(format!("{directory}.{extension}"))
+}
+
+/// The `/`-separated path the flattened name is computed from, relative to `--relative-to`.
+fn name_source(path: &Path, args: &Args) -> String {
+    let source = source_path(path, args);
+    let Some(base) = &args.relative_to else {
+        return source;
+    };
+
+    // Compare canonical paths, so that e.g. `..` in either of the paths is handled
+    let canonical = |path: &Path| {
+        fs::canonicalize(path).unwrap_or_else(|err| {
+            eprintln!("Relative to: {}: {err}", path.display());
+            exit(1);
+        })
+    };
+    let full_path = canonical(&args.path).join(&source);
+    let Ok(relative) = full_path.strip_prefix(canonical(base)) else {
+        eprintln!(
+            "Relative to: {} is not an ancestor of {}",
+            base.display(),
+            path.display()
+        );
+        exit(1);
+    };
+
+    let components: Vec<_> = relative
+        .components()
+        .map(|component| component.as_os_str().to_str().unwrap())
+        .collect();
+    components.join("/")
+}
+
+/// The `/`-separated form of a path relative to the project root, as used for the mapping.
+fn to_source(relative_path: &Path) -> String {
+    relative_path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Determine if the file is LaTeX source, whose include commands are rewritten.
+fn is_tex(path: &Path, args: &Args) -> bool {
+    args.files.is_processed(path)
+}
+
+/// Determine if the include commands of the source file are rewritten.
+///
+/// These are the LaTeX sources by extension, the files without extension
+/// that are included by input-like commands, like `\input{preamble}`,
+/// and the main documents with another extension, like `paper.texw` or `--main paper`.
+/// Other files without extension are copied verbatim.
+fn is_processed(source: &str, args: &Args, mapping: &Mapping) -> bool {
+    is_tex(Path::new(source), args)
+        || mapping.is_extensionless_input(source)
+        || mapping.is_processed_main(source)
+}
+
+/// Write the file at the given path to the output under the given name,
+/// rewriting it if it is processed.
+///
+/// Returns the digest of the written content with `--manifest-hashes`.
+fn write_content(
+    path: &Path,
+    relative_path: &Path,
+    name: &str,
+    args: &Args,
+    mapping: &Mapping,
+    output: &Output,
+    report: &Report,
+) -> Option<String> {
+    if args.flatten_bib_files && !args.no_rewrite && bib::is_bib(relative_path) {
+        let Ok(content) = fs::read_to_string(path) else {
+            return copy_file(path, name, args, output);
+        };
+        let content = bib::rewrite_file_fields(&content, mapping);
+
+        output
+            .write(name, content.as_bytes())
+            .unwrap_or_else(|_| panic!("Failed to create new file {name:?}"));
+        return content_digest(content.as_bytes(), args);
+    }
+
+    if args.no_rewrite || !is_processed(&to_source(relative_path), args, mapping) {
+        return copy_file(path, name, args, output);
+    }
+
+    let Ok(content) = fs::read_to_string(path) else {
+        report.emit(
+            Diagnostic::warning(
+                Code::InvalidUtf8,
+                "Not valid UTF-8, copied as is".to_string(),
+            )
+            .in_file(relative_path.display()),
+        );
+        return copy_file(path, name, args, output);
+    };
+    let mut content = rewrite_content(relative_path, &content, args, mapping, report);
+
+    if mapping.is_preamble_source(&to_source(relative_path)) {
+        match split_preamble(relative_path, &content, args) {
+            Some((preamble, body)) => {
+                output
+                    .write(PREAMBLE_NAME, preamble.as_bytes())
+                    .unwrap_or_else(|_| panic!("Failed to create new file {PREAMBLE_NAME:?}"));
+                content = body;
+            }
+            None => report.emit(
+                Diagnostic::warning(
+                    Code::MissingDocument,
+                    "No \\begin{document} found, so the preamble is not extracted".to_string(),
+                )
+                .in_file(relative_path.display()),
+            ),
+        }
+    }
+
+    let content = content.into_bytes();
+    output
+        .write(name, &content)
+        .unwrap_or_else(|_| panic!("Failed to create new file {name:?}"));
+    content_digest(&content, args)
+}
+
+/// Split the content of a main document into its preamble and the rest,
+/// which inputs the preamble in its place.
+///
+/// Returns `None` if the document has no `\begin{document}`.
+fn split_preamble(relative_path: &Path, content: &str, args: &Args) -> Option<(String, String)> {
+    let mut scanner = LineScanner::new(relative_path);
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        if scanner.scan(line) == LineKind::Latex {
+            if let Some(index) = deps::strip_comment(line).find(r"\begin{document}") {
+                let (preamble, body) = content.split_at(offset + index);
+                let ending = if preamble.ends_with("\r\n") {
+                    "\r\n"
+                } else {
+                    "\n"
+                };
+                let reference = match args.reference_extension {
+                    ReferenceExtension::Add => PREAMBLE_NAME,
+                    ReferenceExtension::Keep | ReferenceExtension::Strip => {
+                        PREAMBLE_NAME.trim_end_matches(".tex")
+                    }
+                };
+                return Some((
+                    preamble.to_string(),
+                    format!("\\input{{{reference}}}{ending}{body}"),
+                ));
+            }
+        }
+        offset += line.len();
+    }
+
+    None
+}
+
+/// Copy the file at the given path to the output under the given name, without rewriting it.
+///
+/// The content is streamed, so large assets like videos are never held in memory.
+/// Returns the digest of the content with `--manifest-hashes`.
+fn copy_file(path: &Path, name: &str, args: &Args, output: &Output) -> Option<String> {
+    let file = File::open(path).expect("Failed to open file");
+    if !args.manifest_hashes {
+        output
+            .copy(name, file, args.copy_buffer_size)
+            .unwrap_or_else(|_| panic!("Failed to create new file {name:?}"));
+        return None;
+    }
+
+    // Hash the content while it is copied, instead of reading the file again
+    let mut reader = HashingReader::new(file);
+    output
+        .copy(name, &mut reader, args.copy_buffer_size)
+        .unwrap_or_else(|_| panic!("Failed to create new file {name:?}"));
+    Some(to_hex(&reader.hasher.finalize()))
+}
+
+/// Add a row for each file referenced by the import to the CSV manifest.
+fn add_manifest_rows(from: &str, import: &Import, report: &Report) {
+    let targets: Vec<_> = if import.targets.is_empty() {
+        vec![String::new()]
+    } else {
+        import.targets.clone()
+    };
+
+    for to in targets {
+        report.add_import(ImportEntry {
+            from: from.to_string(),
+            to,
+            command: import.command.clone(),
+            rewritten: import.rewritten.clone(),
+        });
+    }
+}
+
+/// Rewrite the include commands of a LaTeX buffer, without touching the filesystem.
+///
+/// The `relative_path` is the path of the file relative to the project root.
+/// Its extension determines the syntax, like the code chunks of `.Rnw` files.
+/// As no project files are known, every reference is flattened as written,
+/// independent of the path. This allows editors to preview the rewrite of a file.
+///
+/// Problems like unbalanced `--strip-draft` markers don't stop the rewrite,
+/// the markers are removed as far as possible.
+pub fn rewrite_buffer(relative_path: &Path, content: &str, options: &Args) -> String {
+    let mut mapping = Mapping::default();
+    mapping.set_flatten_case(options.flatten_case);
+    mapping.set_keep_unresolved(has_naming_scheme(options));
+    rewrite_content(
+        relative_path,
+        content,
+        options,
+        &mapping,
+        &Report::new(true),
+    )
+}
+
+/// Rewrite the content of the LaTeX file at the given relative path.
+fn rewrite_content(
+    relative_path: &Path,
+    content: &str,
+    args: &Args,
+    mapping: &Mapping,
+    report: &Report,
+) -> String {
+    let mut scanner = LineScanner::new(relative_path);
+    let mut imports = Vec::new();
+    let mut previous_blank = false;
+    // The line number of the marker starting the draft region we are in
+    let mut draft_start = None;
+    let source = to_source(relative_path);
+    let mut scope = Scope::for_file(&source, mapping);
+    let new_lines: Vec<_> = join_continued_commands(content, args)
+        .into_iter()
+        .filter_map(|(index, line, ending)| {
+            let kind = scanner.scan(line);
+            let warning = |code, message| {
+                Diagnostic::warning(code, message).at(relative_path.display(), index + 1)
+            };
+            if args.strip_draft {
+                let marker = (kind == LineKind::Latex)
+                    .then(|| line.trim_start().strip_prefix('%'))
+                    .flatten()
+                    .map(str::trim);
+                match (marker, draft_start) {
+                    (Some(marker), None) if marker == args.draft_begin => {
+                        draft_start = Some(index + 1);
+                        return None;
+                    }
+                    (Some(marker), Some(start)) if marker == args.draft_begin => {
+                        report.emit(
+                            Diagnostic::error(
+                                Code::DraftRegion,
+                                format!(
+                                    "Nested draft region, the one started on line {start} \
+                                 is not ended yet"
+                                ),
+                            )
+                            .at(relative_path.display(), index + 1),
+                        );
+                        return None;
+                    }
+                    (Some(marker), start) if marker == args.draft_end => {
+                        if start.is_none() {
+                            report.emit(
+                                Diagnostic::error(
+                                    Code::DraftRegion,
+                                    "The end of a draft region which was not started".to_string(),
+                                )
+                                .at(relative_path.display(), index + 1),
+                            );
+                        }
+                        draft_start = None;
+                        return None;
+                    }
+                    (_, Some(_)) => return None,
+                    (_, None) => {}
+                }
+            }
+
+            match kind {
+                LineKind::Latex => {}
+                LineKind::Verbatim | LineKind::Code | LineKind::Disabled => {
+                    if args.explain {
+                        let reason = match kind {
+                            LineKind::Verbatim => SkipReason::Verbatim,
+                            LineKind::Code => SkipReason::Code,
+                            _ => SkipReason::Disabled,
+                        };
+                        trace_imports(line, args, mapping, &scope, |import| {
+                            let skipped =
+                                Import::skipped(&import.command, &import.original, reason);
+                            imports.push((index + 1, skipped))
+                        });
+                    }
+                    previous_blank = false;
+                    return Some((Cow::Borrowed(line), ending));
+                }
+            }
+
+            for command in find_unterminated_imports(line, args) {
+                report.emit(warning(
+                    Code::UnterminatedPath,
+                    format!("The path of \\{command} is missing its closing brace"),
+                ));
+            }
+            for (feature, engine) in engine::find_engine_features(line) {
+                report.add_engine_hint(EngineHint {
+                    source: source.clone(),
+                    line: index + 1,
+                    feature,
+                    engine,
+                });
+            }
+            if args.warn_unsupported {
+                for unsupported in find_unsupported_commands(line, args, mapping) {
+                    report.emit(warning(
+                        Code::UnsupportedCommand,
+                        format!(
+                            "\\{}{{{}}} looks like a reference to {}, \
+                             but \\{} is not an include command, so it is not rewritten. \
+                             Register it in the [commands] section of the config file",
+                            unsupported.command,
+                            unsupported.argument,
+                            unsupported.target,
+                            unsupported.command
+                        ),
+                    ));
+                }
+            }
+
+            scope.define(line, &source, mapping);
+            let mut targets = Vec::new();
+            let rewritten = trace_imports(line, args, mapping, &scope, |import| {
+                if import.skipped == Some(SkipReason::Protected) {
+                    report.emit(warning(
+                        Code::ProtectedPath,
+                        format!(
+                            "The path of \\{}{{{}}} is protected by a wrapper \
+                             which can't be removed, so it is left unchanged",
+                            import.command, import.original
+                        ),
+                    ));
+                }
+                for name in &import.undefined_macros {
+                    report.emit(warning(
+                        Code::UndefinedMacro,
+                        format!(
+                            "The macro \\{name} in the path of \\{} is not defined \
+                             in the file, so it is not expanded",
+                            import.command
+                        ),
+                    ));
+                }
+                for name in &import.absolute_macros {
+                    report.emit(warning(
+                        Code::AbsoluteMacro,
+                        format!(
+                            "The macro \\{name} in the path of \\{} expands to an absolute path",
+                            import.command
+                        ),
+                    ));
+                }
+                for target in import
+                    .targets
+                    .iter()
+                    .filter(|target| mapping.is_oversized(target))
+                {
+                    report.emit(warning(
+                        Code::OversizedTarget,
+                        format!(
+                            "\\{}{{{}}} references {target}, which is not copied \
+                             because it is larger than --max-file-size",
+                            import.command, import.original
+                        ),
+                    ));
+                }
+                for reference in &import.outside_root {
+                    let hint = if args.include_external_refs {
+                        "the file doesn't exist"
+                    } else {
+                        "use --include-external-refs to copy it"
+                    };
+                    report.emit(
+                        Diagnostic::error(
+                            Code::OutsideRoot,
+                            format!(
+                                "\\{}{{{reference}}} points outside the project root, \
+                                 so it is left unchanged and won't be part of the output, {hint}",
+                                import.command
+                            ),
+                        )
+                        .at(relative_path.display(), index + 1),
+                    );
+                }
+                if args.check {
+                    for reference in &import.unresolved {
+                        if !import.outside_root.contains(reference) {
+                            report.emit(warning(
+                                Code::UnresolvedReference,
+                                format!(
+                                    "\\{}{{{reference}}} doesn't resolve to a project file",
+                                    import.command
+                                ),
+                            ));
+                        }
+                    }
+                }
+                for (reference, available) in &import.mismatched_extensions {
+                    let action = if args.fix_extensions {
+                        "rewriting the reference to it"
+                    } else {
+                        "use --fix-extensions to rewrite the reference to it"
+                    };
+                    report.emit(warning(
+                        Code::MismatchedExtension,
+                        format!("{reference} does not exist, but {available} does, {action}"),
+                    ));
+                }
+                if args.manifest_csv.is_some() && import.skipped != Some(SkipReason::Preserved) {
+                    add_manifest_rows(&source, &import, report);
+                }
+                targets.extend(import.targets.clone());
+                imports.push((index + 1, import))
+            });
+            for target in &targets {
+                scope.include(target, mapping);
+            }
+            let rewritten = if args.flatten_href {
+                match flatten_links(&rewritten, mapping, |_| {}) {
+                    Cow::Borrowed(_) => rewritten,
+                    Cow::Owned(line) => Cow::Owned(line),
+                }
+            } else {
+                rewritten
+            };
+
+            let blank = rewritten.trim().is_empty();
+            if args.collapse_blank_lines && blank && previous_blank {
+                return None;
+            }
+            previous_blank = blank;
+
+            if args.strip_trailing_whitespace {
+                let line = match rewritten {
+                    Cow::Borrowed(line) => Cow::Borrowed(line.trim_end()),
+                    Cow::Owned(line) => Cow::Owned(line.trim_end().to_string()),
+                };
+                Some((line, ending))
+            } else {
+                Some((rewritten, ending))
+            }
+        })
+        .collect();
+
+    if let Some(start) = draft_start {
+        report.emit(
+            Diagnostic::error(
+                Code::DraftRegion,
+                "The draft region is not ended".to_string(),
+            )
+            .at(relative_path.display(), start),
+        );
+    }
+
+    if args.explain {
+        explain_imports(relative_path, &imports);
+    }
+
+    let content: String = new_lines
+        .iter()
+        .flat_map(|(line, ending)| [line.as_ref(), ending])
+        .collect();
+    let content = args.transforms.iter().fold(content, |content, transform| {
+        transform.apply(&content).into_owned()
+    });
+    args.normalize_eol.apply(content)
+}
+
+impl Args {
+    /// The options of the given command line flags, e.g. `["--reference-extension", "strip"]`,
+    /// for rewriting buffers with [`rewrite_buffer`]. A `--config` file is not loaded.
+    pub fn from_flags<I, T>(flags: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let required = ["latex-flatten", "--path", ".", "--out", "."].map(OsString::from);
+        Self::try_parse_from(
+            required
+                .into_iter()
+                .chain(flags.into_iter().map(Into::into)),
+        )
+    }
+}
+
+#[cfg(test)]
+impl Args {
+    /// The arguments for flattening `project` into `out`, with the given additional flags.
+    fn with_flags(flags: &[&str]) -> Self {
+        let required = ["latex-flatten", "--path", "project", "--out", "out"];
+        Self::parse_from(required.iter().chain(flags))
+    }
+}
+
+/// A project with the given files by path and content in a temporary directory,
+/// with the arguments for it with the given flags and its mapping.
+#[cfg(test)]
+fn project(files: &[(&str, &str)], flags: &[&str]) -> (tempfile::TempDir, Args, Mapping) {
+    let dir = tempfile::tempdir().unwrap();
+    fixture::write_files(dir.path(), files.iter().copied()).unwrap();
+
+    let mut args = Args::with_flags(flags);
+    args.path = dir.path().into();
+    let report = Report::new(true);
+    let mapping = build_mapping(&walk_project(&args, None, &report), &args, &report);
+    (dir, args, mapping)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_trailing_whitespace() {
+        let content =
+            "\\section{Intro} \t\n\\input{a/b}  \n\\begin{verbatim}  \nx  \n\\end{verbatim}  ";
+        let expected = "\\section{Intro}\n\\input{a__b}\n\\begin{verbatim}\nx  \n\\end{verbatim}  ";
+
+        let args = Args::with_flags(&["--strip-trailing-whitespace"]);
+        let rewritten = rewrite_content(
+            Path::new("main.tex"),
+            content,
+            &args,
+            &Mapping::default(),
+            &Report::new(true),
+        );
+        assert_eq!(rewritten, expected);
+    }
+
+    #[test]
+    fn test_normalize_eol() {
+        let content = "\\input{a/b}\r\nx\n\\includegraphics%\r\n{c/d}\ny\r\n";
+        let rewrite = |flags: &[&str]| {
+            rewrite_content(
+                Path::new("main.tex"),
+                content,
+                &Args::with_flags(flags),
+                &Mapping::default(),
+                &Report::new(true),
+            )
+        };
+
+        assert_eq!(
+            rewrite(&[]),
+            "\\input{a__b}\r\nx\n\\includegraphics%\r\n{c__d}\ny\r\n"
+        );
+        assert_eq!(
+            rewrite(&["--normalize-eol", "lf"]),
+            "\\input{a__b}\nx\n\\includegraphics%\n{c__d}\ny\n"
+        );
+        assert_eq!(
+            rewrite(&["--normalize-eol", "crlf"]),
+            "\\input{a__b}\r\nx\r\n\\includegraphics%\r\n{c__d}\r\ny\r\n"
+        );
+    }
+
+    #[test]
+    fn test_collapse_index() {
+        let (_dir, _, mapping) = project(
+            &[
+                ("main.tex", ""),
+                ("chapters/intro/index.tex", ""),
+                ("chapters/outro/index.tex", ""),
+                ("chapters/outro.tex", ""),
+                ("index.tex", ""),
+            ],
+            &["--collapse-index"],
+        );
+
+        assert_eq!(
+            mapping.name("chapters/intro/index.tex"),
+            Some("chapters__intro.tex")
+        );
+        assert_eq!(
+            mapping.name("chapters/outro/index.tex"),
+            Some("chapters__outro__index.tex")
+        );
+        assert_eq!(mapping.name("index.tex"), Some("index.tex"));
+    }
+
+    #[test]
+    fn test_flatten_case_lower() {
+        let (_dir, _, mapping) = project(
+            &[
+                ("main.tex", ""),
+                ("Figures/Plot.PNG", "plot"),
+                ("fig.png", "fig"),
+                ("Fig.png", "Fig"),
+            ],
+            &["--flatten-case", "lower"],
+        );
+
+        assert_eq!(mapping.name("Figures/Plot.PNG"), Some("figures__plot.png"));
+        assert_eq!(
+            mapping.flatten_reference("Figures/Plot", &["png"], ReferenceExtension::Keep),
+            "figures__plot"
+        );
+        assert_eq!(
+            mapping.flatten_reference("Missing/Plot.png", &["png"], ReferenceExtension::Keep),
+            "missing__plot.png"
+        );
+
+        let collisions = mapping.collisions();
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions[0].kind, CollisionKind::Exact);
+        assert_eq!(
+            collisions[0].sources,
+            ("Fig.png".to_string(), "fig.png".to_string())
+        );
+    }
+
+    #[test]
+    fn test_asset_dir() {
+        let (_dir, _, mapping) = project(
+            &[("main.tex", ""), ("figures/plot.pdf", ""), ("refs.bib", "")],
+            &["--asset-dir", "assets"],
+        );
+
+        assert_eq!(mapping.name("main.tex"), Some("main.tex"));
+        assert_eq!(mapping.name("refs.bib"), Some("refs.bib"));
+        assert_eq!(
+            mapping.flatten_reference("figures/plot", &["pdf"], ReferenceExtension::Keep),
+            "assets/figures__plot"
+        );
+
+        assert_eq!(parse_asset_dir("assets"), Ok("assets".to_string()));
+        assert!(parse_asset_dir("assets/figures").is_err());
+        assert!(parse_asset_dir("..").is_err());
+    }
+
+    #[test]
+    fn test_merge_assets_by_basename() {
+        let (_dir, _, mapping) = project(
+            &[
+                ("a/logo.png", "logo"),
+                ("b/logo.png", "logo"),
+                ("a/plot.pdf", "first"),
+                ("b/plot.pdf", "second"),
+                ("a/intro.tex", ""),
+            ],
+            &["--merge-assets-by-basename"],
+        );
+
+        assert_eq!(mapping.name("a/logo.png"), Some("logo.png"));
+        assert_eq!(mapping.name("b/logo.png"), Some("logo.png"));
+        assert!(mapping.is_duplicate("b/logo.png"));
+        assert_eq!(mapping.name("a/intro.tex"), Some("a__intro.tex"));
+
+        let collisions = mapping.collisions();
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(
+            collisions[0].sources,
+            ("a/plot.pdf".to_string(), "b/plot.pdf".to_string())
+        );
+    }
+
+    #[test]
+    fn test_flatten_fixture() {
+        let dir = tempfile::tempdir().unwrap();
+        fixture::generate(dir.path()).unwrap();
+
+        let mut args = Args::with_flags(&[]);
+        args.path = dir.path().into();
+        let report = Report::new(true);
+        let mapping = build_mapping(&walk_project(&args, None, &report), &args, &report);
+
+        assert!(mapping.collisions().is_empty());
+        assert_eq!(
+            deps::referenced_files(&deps::main_documents(&args, &mapping), &args, &mapping).len(),
+            6
+        );
+        assert_eq!(
+            rewrite_content(
+                Path::new("main.tex"),
+                &fs::read_to_string(dir.path().join("main.tex")).unwrap(),
+                &args,
+                &mapping,
+                &Report::new(true)
+            )
+            .lines()
+            .filter(|line| line.contains("chapters"))
+            .collect::<Vec<_>>(),
+            vec![
+                r"\input{chapters__intro}",
+                r"\include{chapters__results__index}"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rewrite_flat_project() {
+        let (dir, mut args, _) = project(
+            &[
+                (
+                    "main.tex",
+                    "\\input{chapters/intro}\n\\input{chapters__outro}\n\\input{missing/x}\n",
+                ),
+                ("chapters__intro.tex", "\\includegraphics{figures/plot}\n"),
+                ("chapters__outro.tex", "Done\n"),
+                ("figures__plot.pdf", ""),
+            ],
+            &["--in-place", "--rewrite-only", "--in-place-force"],
+        );
+        args.out = dir.path().into();
+        let report = Report::new(true);
+        rewrite_flat_project(&walk_project(&args, None, &report), &args, &report);
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("main.tex")).unwrap(),
+            "\\input{chapters__intro}\n\\input{chapters__outro}\n\\input{missing__x}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("chapters__intro.tex")).unwrap(),
+            "\\includegraphics{figures__plot}\n"
+        );
+    }
+
+    #[test]
+    fn test_namespace_by_type() {
+        let (_dir, _, mapping) = project(
+            &[
+                ("main.tex", ""),
+                ("plot.tex", ""),
+                ("plot.pdf", ""),
+                ("refs.bib", ""),
+                ("data.csv", ""),
+            ],
+            &["--namespace-by-type"],
+        );
+
+        assert_eq!(mapping.name("plot.tex"), Some("tex__plot.tex"));
+        assert_eq!(mapping.name("plot.pdf"), Some("fig__plot.pdf"));
+        assert_eq!(mapping.name("refs.bib"), Some("bib__refs.bib"));
+        assert_eq!(mapping.name("data.csv"), Some("data.csv"));
+        assert_eq!(
+            mapping.flatten_reference("plot", &["tex"], ReferenceExtension::Keep),
+            "tex__plot"
+        );
+        assert_eq!(
+            mapping.flatten_reference("plot", &["pdf", "png"], ReferenceExtension::Keep),
+            "fig__plot"
+        );
+    }
+
+    #[test]
+    fn test_main_with_other_extension() {
+        let files = [
+            (
+                "paper.texw",
+                "\\documentclass{article}\n\\input{chapters/intro}",
+            ),
+            ("notes.texw", "\\input{chapters/intro}"),
+            ("chapters/intro.tex", ""),
+        ];
+        let (_dir, mut args, _) = project(&files, &[]);
+        args.files.main = vec!["texw".to_string()];
+        let report = Report::new(true);
+        let mapping = build_mapping(&walk_project(&args, None, &report), &args, &report);
+
+        assert_eq!(deps::main_documents(&args, &mapping), vec!["paper.texw"]);
+        assert!(is_processed("paper.texw", &args, &mapping));
+        assert!(!is_processed("notes.texw", &args, &mapping));
+
+        let (_dir, args, mapping) = project(&files, &["--main", "notes.texw"]);
+        assert!(is_processed("notes.texw", &args, &mapping));
+        assert_eq!(
+            rewrite_content(
+                Path::new("notes.texw"),
+                "\\input{chapters/intro}",
+                &args,
+                &mapping,
+                &Report::new(true)
+            ),
+            "\\input{chapters__intro}"
+        );
+    }
+
+    #[test]
+    fn test_hashed_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("intro.tex");
+        fs::write(&path, "Intro").unwrap();
+        let digest = file_digest(&path).unwrap();
+        let hash = to_hex(&digest[..4]);
+
+        assert_eq!(
+            hashed_name("chapters__intro.tex", &digest),
+            format!("chapters__intro-{hash}.tex")
+        );
+        assert_eq!(hashed_name("Makefile", &digest), format!("Makefile-{hash}"));
+        assert_eq!(
+            hashed_name(".latexmkrc", &digest),
+            format!(".latexmkrc-{hash}")
+        );
+    }
+
+    #[test]
+    fn test_include_root_docs() {
+        let (_dir, args, _) = project(
+            &[
+                ("main.tex", ""),
+                ("other.tex", ""),
+                ("README.md", ""),
+                ("LICENSE", ""),
+                ("docs/README.md", ""),
+            ],
+            &["--include", "main.tex", "--include-root-docs"],
+        );
+        let report = Report::new(true);
+        let sources: Vec<_> = walk_project(&args, None, &report)
+            .iter()
+            .map(|entry| source_path(entry.path(), &args))
+            .collect();
+
+        assert_eq!(sources, vec!["LICENSE", "README.md", "main.tex"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        fixture::write_files(
+            dir.path(),
+            [
+                ("project/main.tex", ""),
+                ("project/plots/plot.pdf", "plot"),
+                ("shared/logo.pdf", "logo"),
+            ],
+        )
+        .unwrap();
+        std::os::unix::fs::symlink("plots", project.join("figures")).unwrap();
+        std::os::unix::fs::symlink("../shared", project.join("assets")).unwrap();
+
+        let mut args = Args::with_flags(&["--follow-symlinks"]);
+        args.path = project.into();
+        let report = Report::new(true);
+        let mapping = build_mapping(&walk_project(&args, None, &report), &args, &report);
+
+        assert_eq!(mapping.name("plots/plot.pdf"), Some("plots__plot.pdf"));
+        assert_eq!(mapping.name("figures/plot.pdf"), Some("plots__plot.pdf"));
+        assert!(mapping.is_duplicate("figures/plot.pdf"));
+        assert!(!mapping.is_duplicate("plots/plot.pdf"));
+        assert_eq!(mapping.name("assets/logo.pdf"), Some("assets__logo.pdf"));
+        assert!(mapping.collisions().is_empty());
+    }
+
+    #[test]
+    fn test_dedupe_assets_with_unicode_normalization() {
+        let (_dir, _, mapping) = project(
+            &[("figures/plot.pdf", "plot"), ("appendix/plot.pdf", "plot")],
+            &["--dedupe-assets", "--normalize-unicode"],
+        );
+
+        let names: Vec<_> = ["figures/plot", "appendix/plot"]
+            .into_iter()
+            .map(|reference| {
+                mapping
+                    .resolve(reference, &["pdf"])
+                    .and_then(|s| mapping.name(s))
+            })
+            .collect();
+        assert!(names[0].is_some());
+        assert_eq!(names[0], names[1]);
+    }
+
+    #[test]
+    fn test_hashing_reader() {
+        let mut reader = HashingReader::new(io::Cursor::new(b"plot".to_vec()));
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        reader.rewind().unwrap();
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert!(reader.seek(SeekFrom::Current(1)).is_err());
+
+        assert_eq!(
+            reader.hasher.finalize().to_vec(),
+            Sha256::digest(b"plot").to_vec()
+        );
+    }
+
+    #[test]
+    fn test_warn_unreferenced() {
+        let (_dir, args, mapping) = project(
+            &[
+                (
+                    "main.tex",
+                    "\\documentclass{article}\n\\includegraphics{figures/used}",
+                ),
+                ("figures/used.png", "used"),
+                ("figures/copy.png", "used"),
+                ("figures/stray.png", "stray"),
+                ("thesis.sty", ""),
+            ],
+            &["--dedupe-assets"],
+        );
+        let report = Report::new(true);
+        let sources: Vec<_> = walk_project(&args, None, &report)
+            .iter()
+            .map(|entry| source_path(entry.path(), &args))
+            .collect();
+        let report = Report::new(true);
+        warn_unreferenced(&sources, &args, &mapping, &report);
+
+        assert_eq!(report.warning_count(), 1);
+    }
+
+    #[test]
+    fn test_extract_preamble() {
+        let (dir, args, mapping) = project(
+            &[
+                (
+                    "main.tex",
+                    "\\documentclass{article}\n\\input{setup/macros}\n% \\begin{document}\n\\begin{document}\nText\n\\end{document}\n",
+                ),
+                ("setup/macros.tex", ""),
+            ],
+            &["--extract-preamble"],
+        );
+        let out = tempfile::tempdir().unwrap();
+        let report = Report::new(true);
+        write_content(
+            &dir.path().join("main.tex"),
+            Path::new("main.tex"),
+            "main.tex",
+            &args,
+            &mapping,
+            &Output::directory(out.path()),
+            &report,
+        );
+
+        assert_eq!(
+            fs::read_to_string(out.path().join("preamble.tex")).unwrap(),
+            "\\documentclass{article}\n\\input{setup__macros}\n% \\begin{document}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(out.path().join("main.tex")).unwrap(),
+            "\\input{preamble}\n\\begin{document}\nText\n\\end{document}\n"
+        );
+        assert_eq!(report.warning_count(), 0);
+    }
+
+    #[test]
+    fn test_write_invalid_utf8() {
+        let (dir, args, mapping) = project(&[("main.tex", "")], &[]);
+        fs::write(dir.path().join("main.tex"), b"\\input{a}\n\xff\n").unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let report = Report::new(true);
+        write_content(
+            &dir.path().join("main.tex"),
+            Path::new("main.tex"),
+            "main.tex",
+            &args,
+            &mapping,
+            &Output::directory(out.path()),
+            &report,
+        );
+
+        assert_eq!(
+            fs::read(out.path().join("main.tex")).unwrap(),
+            b"\\input{a}\n\xff\n"
+        );
+        assert_eq!(report.warning_count(), 1);
+    }
+
+    #[test]
+    fn test_extract_preamble_without_document() {
+        let args = Args::with_flags(&["--extract-preamble"]);
+        assert_eq!(
+            split_preamble(Path::new("main.tex"), "\\documentclass{article}\n", &args),
+            None
+        );
+    }
+
+    #[test]
+    fn test_rewrite_buffer() {
+        let options = Args::from_flags(["--reference-extension", "strip"]).unwrap();
+
+        assert_eq!(
+            rewrite_buffer(
+                Path::new("chapters/intro.tex"),
+                "\\input{parts/a.tex}\n\\includegraphics{figures/plot.pdf}",
+                &options
+            ),
+            "\\input{parts__a}\n\\includegraphics{figures__plot.pdf}"
+        );
+        assert!(Args::from_flags(["--unknown"]).is_err());
+    }
+
+    #[test]
+    fn test_no_rewrite_conflicts() {
+        assert!(Args::from_flags(["--no-rewrite"]).is_ok());
+        assert!(Args::from_flags(["--no-rewrite", "--normalize-eol", "lf"]).is_err());
+    }
+
+    #[test]
+    fn test_iffalse_is_not_rewritten() {
+        let content = "\\input{a/b}\n\\iffalse\n\\input{old/draft}\n\\fi\n\\input{c/d}";
+        let expected = "\\input{a__b}\n\\iffalse\n\\input{old/draft}\n\\fi\n\\input{c__d}";
+
+        let rewritten = rewrite_content(
+            Path::new("main.tex"),
+            content,
+            &Args::with_flags(&[]),
+            &Mapping::default(),
+            &Report::new(true),
+        );
+        assert_eq!(rewritten, expected);
+    }
+
+    #[test]
+    fn test_strip_draft() {
+        let content = "Intro\n%DRAFT-BEGIN\nReviewer note \\input{notes}\n% DRAFT-END\nBody\r\n\
+                       \\begin{verbatim}\n%DRAFT-BEGIN\n\\end{verbatim}\n";
+
+        let args = Args::with_flags(&["--strip-draft"]);
+        assert_eq!(
+            rewrite_buffer(Path::new("main.tex"), content, &args),
+            "Intro\nBody\r\n\\begin{verbatim}\n%DRAFT-BEGIN\n\\end{verbatim}\n"
+        );
+
+        let args = Args::with_flags(&[
+            "--strip-draft",
+            "--draft-begin",
+            "TODO",
+            "--draft-end",
+            "/TODO",
+        ]);
+        assert_eq!(
+            rewrite_buffer(Path::new("main.tex"), "A\n%TODO\nB\n%/TODO\n", &args),
+            "A\n"
+        );
+
+        let args = Args::with_flags(&["--strip-draft"]);
+        let report = Report::new(true);
+        let rewritten = rewrite_content(
+            Path::new("main.tex"),
+            "A\n%DRAFT-END\nB\n%DRAFT-BEGIN\n%DRAFT-BEGIN\nC\n",
+            &args,
+            &Mapping::default(),
+            &report,
+        );
+        assert_eq!(rewritten, "A\nB\n");
+        assert_eq!(report.error_count(), 3);
+    }
+
+    #[test]
+    fn test_reference_outside_root() {
+        let (_dir, args, mapping) = project(&[("main.tex", ""), ("outside/x.tex", "")], &[]);
+        let report = Report::new(true);
+        let rewritten = rewrite_content(
+            Path::new("main.tex"),
+            "\\input{../outside/x}\n\\input{outside/x}\n",
+            &args,
+            &mapping,
+            &report,
+        );
+
+        assert_eq!(rewritten, "\\input{../outside/x}\n\\input{outside__x}\n");
+        assert_eq!(report.error_count(), 1);
+    }
+
+    #[test]
+    fn test_collapse_blank_lines() {
+        let content = "a\n\n  \n\nb\n\\begin{verbatim}\n\n\n\\end{verbatim}\n\n\nc";
+        let expected = "a\n\nb\n\\begin{verbatim}\n\n\n\\end{verbatim}\n\nc";
+
+        let args = Args::with_flags(&["--collapse-blank-lines"]);
+        let rewritten = rewrite_content(
+            Path::new("main.tex"),
+            content,
+            &args,
+            &Mapping::default(),
+            &Report::new(true),
+        );
+        assert_eq!(rewritten, expected);
+    }
+}
@@ -0,0 +1,122 @@
+use regex::{Captures, Regex};
+use std::{borrow::Cow, collections::HashMap, sync::LazyLock};
+
+/// Definitions of macros without parameters, like `\def\name{value}`
+/// or `\newcommand{\name}{value}`.
+static DEFINITION_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"\\def[ \t]*\\([A-Za-z@]+)[ \t]*\{([^{}]*)\}|\\(?:re)?newcommand[ \t]*\{?\\([A-Za-z@]+)\}?[ \t]*\{([^{}]*)\}",
+    )
+    .unwrap()
+});
+
+/// A control word, like `\name`, with the spaces TeX skips after it.
+static MACRO_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\\([A-Za-z@]+)[ \t]*").unwrap());
+
+/// The simple macros defined so far in a file, used to expand include paths.
+#[derive(Debug, Default, Clone)]
+pub struct Macros {
+    values: HashMap<String, String>,
+}
+
+impl Macros {
+    /// Record the macro definitions of the line.
+    pub fn define(&mut self, line: &str) {
+        let line = crate::deps::strip_comment(line);
+
+        for capture in DEFINITION_REGEX.captures_iter(line) {
+            let name = capture.get(1).or(capture.get(3)).unwrap().as_str();
+            let value = capture.get(2).or(capture.get(4)).unwrap().as_str();
+            self.values.insert(name.to_string(), value.to_string());
+        }
+    }
+
+    /// Replace the defined macros in the path by their values, without expanding them further.
+    ///
+    /// Like in TeX, the spaces after a macro are skipped, so a root prefix macro like
+    /// `\def\projroot{./}` is concatenated with the rest, e.g. `\projroot chapters/intro`.
+    /// Also returns the names of the macros which are not defined, which are left as written.
+    pub fn expand<'a>(&self, path: &'a str) -> (Cow<'a, str>, Vec<String>) {
+        let mut undefined = Vec::new();
+        let expanded = MACRO_REGEX.replace_all(path, |capture: &Captures| {
+            let name = &capture[1];
+            match self.values.get(name) {
+                Some(value) => value.clone(),
+                None => {
+                    undefined.push(name.to_string());
+                    capture[0].to_string()
+                }
+            }
+        });
+
+        (expanded, undefined)
+    }
+
+    /// The names of the defined macros in the path whose value is an absolute path.
+    pub fn absolute_macros(&self, path: &str) -> Vec<String> {
+        MACRO_REGEX
+            .captures_iter(path)
+            .map(|capture| capture[1].to_string())
+            .filter(|name| {
+                self.values.get(name).is_some_and(|value| {
+                    let value = value.trim_start();
+                    let bytes = value.as_bytes();
+                    value.starts_with(['/', '\\'])
+                        || (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_root_prefix() {
+        let mut macros = Macros::default();
+        macros.define(r"\def\projroot{./} \def\@abs{/home/me/}");
+
+        assert_eq!(
+            macros.expand(r"\projroot chapters/intro"),
+            (Cow::Borrowed("./chapters/intro"), vec![])
+        );
+        assert_eq!(
+            macros.expand(r"\projroot  \undefined  x"),
+            (
+                Cow::Borrowed(r"./\undefined  x"),
+                vec!["undefined".to_string()]
+            )
+        );
+        assert_eq!(
+            macros.absolute_macros(r"\@abs paper/\projroot"),
+            vec!["@abs"]
+        );
+    }
+
+    #[test]
+    fn test_expand() {
+        let mut macros = Macros::default();
+        macros.define(r"\def\buildmode{release} \newcommand{\figdir}{figures/\buildmode}");
+        macros.define(r"\newcommand\chapterdir{chapters} % \def\buildmode{debug}");
+        macros.define(r"\newcommand{\param}[1]{#1}");
+
+        assert_eq!(
+            macros.expand(r"build/\buildmode/summary"),
+            (Cow::Borrowed("build/release/summary"), vec![])
+        );
+        assert_eq!(
+            macros.expand(r"\figdir/\chapterdir/\jobname"),
+            (
+                Cow::Borrowed(r"figures/\buildmode/chapters/\jobname"),
+                vec!["jobname".to_string()]
+            )
+        );
+        assert_eq!(
+            macros.expand(r"\param/x"),
+            (Cow::Borrowed(r"\param/x"), vec!["param".to_string()])
+        );
+    }
+}
@@ -0,0 +1,744 @@
+use crate::{macros::Macros, FlattenCase, ReferenceExtension};
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap, HashSet},
+    path::{Component, Path},
+};
+use unicode_normalization::UnicodeNormalization;
+
+/// How the output names of two different source files conflict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionKind {
+    /// The names are identical, so one file would overwrite the other.
+    Exact,
+    /// The names only differ in case, so they collide on case-insensitive file systems.
+    Case,
+}
+
+/// Two source files with conflicting output names.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Collision {
+    pub kind: CollisionKind,
+    /// The source paths, in order.
+    pub sources: (String, String),
+    /// The output names, in the order of the sources.
+    pub names: (String, String),
+}
+
+/// The output names of the project files.
+///
+/// This is the single source of truth for naming:
+/// both the copied files and the rewritten references are derived from it,
+/// so every reference agrees on the name of its target.
+#[derive(Debug, Default)]
+pub struct Mapping {
+    /// The output names, keyed by the source path relative to the project root with `/` separators.
+    names: HashMap<String, String>,
+    /// Sources whose content is already written under the name of another source.
+    duplicates: HashSet<String>,
+    /// The sources keyed by their NFC form, if references are matched after Unicode normalization.
+    nfc_sources: Option<HashMap<String, String>>,
+    graphics_paths: GraphicsPaths,
+    /// The sources outside of the project root, included with `--include-external-refs`.
+    externals: BTreeSet<String>,
+    /// The sources without extension included by input-like commands, which are LaTeX source.
+    extensionless_inputs: HashSet<String>,
+    /// The main documents which are processed although their extension is not.
+    processed_mains: HashSet<String>,
+    /// The sources which are not copied because they are larger than `--max-file-size`.
+    oversized: HashSet<String>,
+    /// The macros defined by the local class and package files, like a project root macro.
+    package_macros: Macros,
+    /// The case of the names of unresolved references, like the names of the sources.
+    flatten_case: FlattenCase,
+    /// Leave unresolved references unchanged, as the names of the sources are not
+    /// just their paths with `/` replaced, and the scheme needs the file.
+    keep_unresolved: bool,
+    /// The main document whose preamble is written to its own file, with `--extract-preamble`.
+    preamble_source: Option<String>,
+}
+
+/// The `\graphicspath` directories in effect at the start and at the end of each file
+/// reachable from the main documents, keyed by source path.
+#[derive(Debug, Default)]
+pub struct GraphicsPaths {
+    pub start: HashMap<String, Vec<String>>,
+    pub end: HashMap<String, Vec<String>>,
+}
+
+impl Mapping {
+    /// Match references to sources after normalizing both to NFC,
+    /// so that decomposed and composed accented names refer to the same file.
+    ///
+    /// Must be called before inserting sources.
+    pub fn with_unicode_normalization() -> Self {
+        Self {
+            nfc_sources: Some(HashMap::new()),
+            ..Self::default()
+        }
+    }
+
+    /// Register the output name of a source file.
+    pub fn insert(&mut self, source: String, name: String) {
+        if let Some(nfc_sources) = &mut self.nfc_sources {
+            nfc_sources.insert(source.nfc().collect(), source.clone());
+        }
+        self.names.insert(source, name);
+    }
+
+    /// Find the registered source with the given path.
+    fn find_source(&self, candidate: &str) -> Option<&str> {
+        let source = match &self.nfc_sources {
+            Some(nfc_sources) => nfc_sources.get(&candidate.nfc().collect::<String>())?,
+            None => candidate,
+        };
+
+        self.names
+            .get_key_value(source)
+            .map(|(source, _)| source.as_str())
+    }
+
+    /// Register a file outside of the project root, keyed by its normalized reference,
+    /// like `../shared/logo.pdf`.
+    pub fn insert_external(&mut self, reference: &str, name: String) {
+        let source = normalize_external(reference);
+        self.externals.insert(source.clone());
+        self.insert(source, name);
+    }
+
+    /// The registered files outside of the project root, sorted.
+    pub fn externals(&self) -> impl Iterator<Item = &str> {
+        self.externals.iter().map(String::as_str)
+    }
+
+    /// Register that the source without extension is included by an input-like command.
+    pub fn insert_extensionless_input(&mut self, source: String) {
+        self.extensionless_inputs.insert(source);
+    }
+
+    /// Determine if the source has no extension and is included by an input-like command.
+    pub fn is_extensionless_input(&self, source: &str) -> bool {
+        self.extensionless_inputs.contains(source)
+    }
+
+    /// Register that the source is a main document which is processed whatever its extension.
+    pub fn insert_processed_main(&mut self, source: String) {
+        self.processed_mains.insert(source);
+    }
+
+    /// Determine if the source is a main document which is processed whatever its extension.
+    pub fn is_processed_main(&self, source: &str) -> bool {
+        self.processed_mains.contains(source)
+    }
+
+    /// Register that the source is too large to be copied.
+    pub fn insert_oversized(&mut self, source: String) {
+        self.oversized.insert(source);
+    }
+
+    /// Determine if the source is too large to be copied.
+    pub fn is_oversized(&self, source: &str) -> bool {
+        self.oversized.contains(source)
+    }
+
+    /// Register a source file that is identical to an already registered file.
+    ///
+    /// It will not be copied, and references to it point to the canonical file instead.
+    pub fn insert_duplicate(&mut self, source: String, canonical: String) {
+        let name = self.names[&canonical].clone();
+        self.duplicates.insert(source.clone());
+        self.insert(source, name);
+    }
+
+    /// The output name of the given source file.
+    pub fn name(&self, source: &str) -> Option<&str> {
+        self.names.get(source).map(String::as_str)
+    }
+
+    /// All registered source files, in arbitrary order.
+    pub fn sources(&self) -> impl Iterator<Item = &str> {
+        self.names.keys().map(String::as_str)
+    }
+
+    /// Determine if the reference points to a directory containing source files.
+    pub fn has_directory(&self, reference: &str) -> bool {
+        normalize(reference.trim()).is_some_and(|directory| {
+            let prefix = format!("{directory}/");
+            self.sources().any(|source| source.starts_with(&prefix))
+        })
+    }
+
+    /// Register the `\graphicspath` directories in effect in the files.
+    pub fn set_graphics_paths(&mut self, graphics_paths: GraphicsPaths) {
+        self.graphics_paths = graphics_paths;
+    }
+
+    /// Set the case of the names of references which don't resolve to a source.
+    ///
+    /// The names of the sources are registered in the same case.
+    pub fn set_flatten_case(&mut self, flatten_case: FlattenCase) {
+        self.flatten_case = flatten_case;
+    }
+
+    /// Leave the references which don't resolve to a source unchanged,
+    /// instead of flattening them by replacing the separators.
+    pub fn set_keep_unresolved(&mut self, keep_unresolved: bool) {
+        self.keep_unresolved = keep_unresolved;
+    }
+
+    /// Set the main document whose preamble is written to its own file.
+    pub fn set_preamble_source(&mut self, source: String) {
+        self.preamble_source = Some(source);
+    }
+
+    /// Determine if the preamble of the source file is written to its own file.
+    pub fn is_preamble_source(&self, source: &str) -> bool {
+        self.preamble_source.as_deref() == Some(source)
+    }
+
+    /// Register the macros defined by the local class and package files.
+    pub fn set_package_macros(&mut self, macros: Macros) {
+        self.package_macros = macros;
+    }
+
+    /// The macros defined by the local class and package files, which every file can use.
+    pub fn package_macros(&self) -> &Macros {
+        &self.package_macros
+    }
+
+    /// The `\graphicspath` directories in effect at the start of the source file,
+    /// as set by the files including it.
+    pub fn graphics_paths_at_start(&self, source: &str) -> &[String] {
+        self.graphics_paths
+            .start
+            .get(source)
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// The `\graphicspath` directories in effect at the end of the source file,
+    /// if it is reachable from the main documents.
+    pub fn graphics_paths_at_end(&self, source: &str) -> Option<&[String]> {
+        self.graphics_paths.end.get(source).map(Vec::as_slice)
+    }
+
+    /// Determine if the source file is a duplicate which must not be copied.
+    pub fn is_duplicate(&self, source: &str) -> bool {
+        self.duplicates.contains(source)
+    }
+
+    /// Find all pairs of written files whose names conflict, sorted by source path.
+    pub fn collisions(&self) -> Vec<Collision> {
+        let mut written: Vec<_> = self
+            .names
+            .iter()
+            .filter(|(source, _)| !self.duplicates.contains(*source))
+            .collect();
+        written.sort();
+
+        // The first source written to each (case-folded) name
+        let mut exact = HashMap::new();
+        let mut folded = HashMap::new();
+        let mut collisions = Vec::new();
+
+        for (source, name) in written {
+            let (kind, first) = if let Some(first) = exact.get(name) {
+                (CollisionKind::Exact, first)
+            } else if let Some(first) = folded.get(&name.to_lowercase()) {
+                (CollisionKind::Case, first)
+            } else {
+                exact.insert(name, source);
+                folded.insert(name.to_lowercase(), source);
+                continue;
+            };
+
+            collisions.push(Collision {
+                kind,
+                sources: (String::clone(first), source.clone()),
+                names: (self.names[*first].clone(), name.clone()),
+            });
+        }
+
+        collisions
+    }
+
+    /// Find the source file a reference points to.
+    ///
+    /// Like LaTeX, the literal path is tried first, then the path with each of the default extensions.
+    pub fn resolve(&self, reference: &str, extensions: &[&str]) -> Option<&str> {
+        let reference = normalize(reference.trim()).unwrap_or_else(|| {
+            // Only matches the files included with `--include-external-refs`
+            normalize_external(reference.trim())
+        });
+
+        if let Some(source) = self.find_source(&reference) {
+            return Some(source);
+        }
+
+        extensions
+            .iter()
+            .find_map(|extension| self.find_source(&format!("{reference}.{extension}")))
+    }
+
+    /// Find the source file a dangling reference with a default extension was likely meant to
+    /// point to, i.e. the same path with another of the default `extensions`.
+    ///
+    /// Returns `None` if the reference resolves or no such file exists.
+    pub fn resolve_other_extension(&self, reference: &str, extensions: &[&str]) -> Option<&str> {
+        if self.resolve(reference, extensions).is_some() {
+            return None;
+        }
+
+        let stem = strip_extension(reference.trim(), extensions);
+        if stem == reference.trim() {
+            return None;
+        }
+
+        self.resolve(stem, extensions)
+    }
+
+    /// Rewrite a reference to point to the flattened file.
+    ///
+    /// The `policy` determines if the extension is written.
+    /// It can only be omitted if it is one of the default `extensions`, which LaTeX adds back.
+    /// References that don't resolve to a project file are flattened naively by replacing the separators,
+    /// unless they are kept unchanged, see [`Mapping::set_keep_unresolved`].
+    /// References outside of the project root, like `../notes` or `/home/me/fig.pdf`,
+    /// are always kept unchanged.
+    pub fn flatten_reference(
+        &self,
+        reference: &str,
+        extensions: &[&str],
+        policy: ReferenceExtension,
+    ) -> String {
+        let Some(source) = self.resolve(reference, extensions) else {
+            // Flattening would mangle the path into a dangling name like `..__notes` or `C:__Users__fig.pdf`
+            if is_outside_root(reference) || self.keep_unresolved {
+                return reference.to_string();
+            }
+
+            let mut flattened = self.flatten_case.apply(reference.replace('/', "__"));
+            if self.nfc_sources.is_some() {
+                flattened = flattened.nfc().collect();
+            }
+            return match policy {
+                ReferenceExtension::Strip => strip_extension(&flattened, extensions).to_string(),
+                ReferenceExtension::Keep | ReferenceExtension::Add => flattened,
+            };
+        };
+        let name = &self.names[source];
+
+        let omit_extension = match policy {
+            ReferenceExtension::Keep => !reference_has_extension(reference, source),
+            ReferenceExtension::Strip => true,
+            ReferenceExtension::Add => false,
+        };
+
+        if omit_extension {
+            if let Some(extension) = Path::new(source)
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .filter(|ext| extensions.contains(ext))
+            {
+                if let Some(stem) = name
+                    .strip_suffix(extension)
+                    .and_then(|s| s.strip_suffix('.'))
+                {
+                    return stem.to_string();
+                }
+            }
+        }
+
+        name.clone()
+    }
+}
+
+/// Remove the extension of the reference if it is one of the default extensions.
+fn strip_extension<'a>(reference: &'a str, extensions: &[&str]) -> &'a str {
+    extensions
+        .iter()
+        .find_map(|extension| {
+            reference
+                .strip_suffix(extension)
+                .and_then(|stem| stem.strip_suffix('.'))
+        })
+        .unwrap_or(reference)
+}
+
+/// Determine if the reference already contained the extension of the resolved source.
+fn reference_has_extension(reference: &str, source: &str) -> bool {
+    Path::new(reference.trim()).extension() == Path::new(source).extension()
+}
+
+/// Determine if the reference is absolute or escapes the project root, e.g. `../notes`.
+pub fn is_outside_root(reference: &str) -> bool {
+    normalize(reference.trim()).is_none()
+}
+
+/// Determine if the reference is an absolute Unix path like `/home/me/fig.pdf`,
+/// or a Windows path like `C:/Users/me/fig.pdf` or `C:\Users\me\fig.pdf`,
+/// independent of the platform. A leading `\` starts a macro, not a path.
+pub fn is_absolute(reference: &str) -> bool {
+    let reference = reference.trim();
+    reference.starts_with('/') || drive_letter(reference).is_some()
+}
+
+/// The drive letter of a Windows absolute path.
+fn drive_letter(reference: &str) -> Option<char> {
+    let mut chars = reference.chars();
+    let drive = chars.next().filter(char::is_ascii_alphabetic)?;
+    (chars.next() == Some(':') && matches!(chars.next(), Some('/' | '\\'))).then_some(drive)
+}
+
+/// Lexically normalize a relative reference to the `/`-separated form of the mapping keys.
+///
+/// Returns `None` if the reference is absolute or escapes the project root.
+fn normalize(reference: &str) -> Option<String> {
+    if is_absolute(reference) {
+        return None;
+    }
+
+    let mut components = Vec::new();
+
+    for component in Path::new(reference).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                components.pop()?;
+            }
+            Component::Normal(name) => components.push(name.to_str()?),
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(components.join("/"))
+}
+
+/// Lexically normalize a reference outside of the project root,
+/// keeping the leading `..` of relative references and the `/` of absolute ones.
+/// Windows paths keep their drive, like `C:/Users/me/fig.pdf`.
+fn normalize_external(reference: &str) -> String {
+    if let Some(drive) = drive_letter(reference) {
+        return format!(
+            "{drive}:{}",
+            normalize_external(&reference[2..].replace('\\', "/"))
+        );
+    }
+
+    let mut components: Vec<Cow<str>> = Vec::new();
+
+    for component in Path::new(reference).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match components.last().map(Cow::as_ref) {
+                Some("..") | None => components.push(Cow::Borrowed("..")),
+                Some("") => {}
+                Some(_) => {
+                    components.pop();
+                }
+            },
+            Component::Normal(name) => components.push(name.to_string_lossy()),
+            Component::RootDir | Component::Prefix(_) => {
+                components.clear();
+                components.push(Cow::Borrowed(""));
+            }
+        }
+    }
+
+    if components.len() == 1 && components[0].is_empty() {
+        return "/".to_string();
+    }
+    components.join("/")
+}
+
+/// The flattened name of a file outside of the project root,
+/// e.g. `external__shared__logo.pdf` for `../shared/logo.pdf`.
+pub fn external_name(reference: &str) -> String {
+    let source = normalize_external(reference);
+    let components: Vec<_> = source
+        .split('/')
+        .filter(|component| !component.is_empty() && *component != "..")
+        .map(|component| component.trim_end_matches(':'))
+        .collect();
+    format!("external__{}", components.join("__"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping() -> Mapping {
+        let mut mapping = Mapping::default();
+        mapping.insert("figures/plot.pdf".into(), "figures__plot.pdf".into());
+        mapping.insert_duplicate("appendix/plot.pdf".into(), "figures/plot.pdf".into());
+        mapping.insert("chapters/intro.tex".into(), "chapters__intro.tex".into());
+        mapping.insert("preamble".into(), "preamble".into());
+        mapping
+    }
+
+    fn flatten_tex(mapping: &Mapping, reference: &str, policy: ReferenceExtension) -> String {
+        mapping.flatten_reference(reference, &["tex"], policy)
+    }
+
+    #[test]
+    fn test_flatten_reference_unresolved() {
+        let mapping = mapping();
+
+        assert_eq!(
+            mapping.flatten_reference("content/background", &["tex"], ReferenceExtension::Keep),
+            "content__background"
+        );
+    }
+
+    #[test]
+    fn test_flatten_reference_outside_root() {
+        let mapping = mapping();
+
+        assert_eq!(
+            mapping.flatten_reference("../outside/x", &["tex"], ReferenceExtension::Strip),
+            "../outside/x"
+        );
+        assert_eq!(
+            mapping.flatten_reference(
+                "chapters/../../notes.tex",
+                &["tex"],
+                ReferenceExtension::Keep
+            ),
+            "chapters/../../notes.tex"
+        );
+    }
+
+    #[test]
+    fn test_flatten_reference_keep_unresolved() {
+        let mut mapping = mapping();
+        mapping.set_keep_unresolved(true);
+
+        assert_eq!(
+            mapping.flatten_reference("content/background", &["tex"], ReferenceExtension::Strip),
+            "content/background"
+        );
+        assert_eq!(
+            mapping.flatten_reference("chapters/intro", &["tex"], ReferenceExtension::Keep),
+            "chapters__intro"
+        );
+    }
+
+    #[test]
+    fn test_flatten_reference_duplicate() {
+        let mapping = mapping();
+
+        assert_eq!(
+            mapping.flatten_reference("appendix/plot.pdf", &["pdf"], ReferenceExtension::Keep),
+            "figures__plot.pdf"
+        );
+    }
+
+    #[test]
+    fn test_flatten_reference_duplicate_without_extension() {
+        let mapping = mapping();
+
+        assert_eq!(
+            mapping.flatten_reference("./appendix/plot", &["pdf", "png"], ReferenceExtension::Keep),
+            "figures__plot"
+        );
+    }
+
+    #[test]
+    fn test_reference_extension_keep() {
+        let mapping = mapping();
+        let policy = ReferenceExtension::Keep;
+
+        assert_eq!(
+            flatten_tex(&mapping, "chapters/intro", policy),
+            "chapters__intro"
+        );
+        assert_eq!(
+            flatten_tex(&mapping, "chapters/intro.tex", policy),
+            "chapters__intro.tex"
+        );
+    }
+
+    #[test]
+    fn test_reference_extension_strip() {
+        let mapping = mapping();
+        let policy = ReferenceExtension::Strip;
+
+        assert_eq!(
+            flatten_tex(&mapping, "chapters/intro", policy),
+            "chapters__intro"
+        );
+        assert_eq!(
+            flatten_tex(&mapping, "chapters/intro.tex", policy),
+            "chapters__intro"
+        );
+        assert_eq!(
+            flatten_tex(&mapping, "missing/file.tex", policy),
+            "missing__file"
+        );
+    }
+
+    #[test]
+    fn test_reference_extension_add() {
+        let mapping = mapping();
+        let policy = ReferenceExtension::Add;
+
+        assert_eq!(
+            flatten_tex(&mapping, "chapters/intro", policy),
+            "chapters__intro.tex"
+        );
+        assert_eq!(
+            flatten_tex(&mapping, "chapters/intro.tex", policy),
+            "chapters__intro.tex"
+        );
+        // Files without extension are input literally
+        assert_eq!(flatten_tex(&mapping, "preamble", policy), "preamble");
+    }
+
+    #[test]
+    fn test_unicode_normalization() {
+        // The source name is decomposed, like on macOS: e + combining acute accent
+        let decomposed = "figures/caf\u{65}\u{301}.pdf";
+        let composed = "figures/caf\u{e9}";
+        let mut mapping = Mapping::with_unicode_normalization();
+        mapping.insert(decomposed.into(), "figures__caf\u{e9}.pdf".into());
+
+        assert_eq!(mapping.resolve(composed, &["pdf"]), Some(decomposed));
+        assert_eq!(
+            mapping.flatten_reference(composed, &["pdf"], ReferenceExtension::Keep),
+            "figures__caf\u{e9}"
+        );
+        assert_eq!(Mapping::default().resolve(composed, &["pdf"]), None);
+    }
+
+    #[test]
+    fn test_unicode_normalization_duplicate() {
+        let mut mapping = Mapping::with_unicode_normalization();
+        mapping.insert("appendix/plot.pdf".into(), "appendix__plot.pdf".into());
+        mapping.insert_duplicate("figures/plot.pdf".into(), "appendix/plot.pdf".into());
+
+        assert_eq!(
+            mapping.flatten_reference("figures/plot", &["pdf"], ReferenceExtension::Keep),
+            "appendix__plot"
+        );
+    }
+
+    #[test]
+    fn test_resolve_extensionless() {
+        let mut mapping = Mapping::default();
+        mapping.insert("preamble".into(), "preamble".into());
+        mapping.insert("setup.tex".into(), "setup.tex".into());
+        let flatten = |mapping: &Mapping, reference| {
+            mapping.flatten_reference(reference, &["tex"], ReferenceExtension::Keep)
+        };
+
+        assert_eq!(mapping.resolve("preamble", &["tex"]), Some("preamble"));
+        assert_eq!(flatten(&mapping, "preamble"), "preamble");
+        assert_eq!(mapping.resolve("setup", &["tex"]), Some("setup.tex"));
+        assert_eq!(flatten(&mapping, "setup"), "setup");
+
+        // Like LaTeX, the literal name wins over the name with `.tex` appended
+        mapping.insert("preamble.tex".into(), "preamble.tex".into());
+        assert_eq!(mapping.resolve("preamble", &["tex"]), Some("preamble"));
+        assert_eq!(
+            mapping.resolve("preamble.tex", &["tex"]),
+            Some("preamble.tex")
+        );
+    }
+
+    #[test]
+    fn test_is_absolute() {
+        assert!(is_absolute("/home/me/fig.pdf"));
+        assert!(is_absolute("C:/Users/me/fig.pdf"));
+        assert!(is_absolute(r"c:\Users\me\fig.pdf"));
+        assert!(!is_absolute("C:fig.pdf"));
+        assert!(!is_absolute("figures/C:/fig.pdf"));
+        assert!(is_outside_root("C:/Users/me/fig.pdf"));
+        assert_eq!(
+            external_name(r"C:\Users\me\.\fig.pdf"),
+            "external__C__Users__me__fig.pdf"
+        );
+    }
+
+    #[test]
+    fn test_externals() {
+        let mut mapping = Mapping::default();
+        mapping.insert_external(
+            "../shared/./logo.pdf",
+            external_name("../shared/./logo.pdf"),
+        );
+        mapping.insert_external("/home/me/refs.bib", external_name("/home/me/refs.bib"));
+
+        assert_eq!(
+            mapping.externals().collect::<Vec<_>>(),
+            vec!["../shared/logo.pdf", "/home/me/refs.bib"]
+        );
+        assert_eq!(
+            mapping.flatten_reference(
+                "chapters/../../shared/logo",
+                &["pdf"],
+                ReferenceExtension::Keep
+            ),
+            "external__shared__logo"
+        );
+        assert_eq!(
+            mapping.flatten_reference("/home/me/refs", &["bib"], ReferenceExtension::Keep),
+            "external__home__me__refs"
+        );
+        assert_eq!(mapping.resolve("../other/logo", &["pdf"]), None);
+    }
+
+    #[test]
+    fn test_is_outside_root() {
+        assert!(is_outside_root("../../secret/notes"));
+        assert!(is_outside_root("chapters/../../notes"));
+        assert!(is_outside_root("/etc/passwd"));
+        assert!(!is_outside_root("chapters/../notes"));
+        assert!(!is_outside_root("./notes"));
+    }
+
+    #[test]
+    fn test_resolve_other_extension() {
+        let mut mapping = Mapping::default();
+        mapping.insert("figures/plot.pdf".into(), "figures__plot.pdf".into());
+        let extensions = ["pdf", "eps"];
+
+        assert_eq!(
+            mapping.resolve_other_extension("figures/plot.eps", &extensions),
+            Some("figures/plot.pdf")
+        );
+        assert_eq!(
+            mapping.resolve_other_extension("figures/plot.pdf", &extensions),
+            None
+        );
+        assert_eq!(
+            mapping.resolve_other_extension("figures/plot", &extensions),
+            None
+        );
+        assert_eq!(
+            mapping.resolve_other_extension("figures/other.eps", &extensions),
+            None
+        );
+    }
+
+    #[test]
+    fn test_collisions() {
+        let mut mapping = mapping();
+        mapping.insert("chapters__intro.tex".into(), "chapters__intro.tex".into());
+        mapping.insert("Figures/plot.pdf".into(), "Figures__plot.pdf".into());
+
+        assert_eq!(
+            mapping.collisions(),
+            vec![
+                Collision {
+                    kind: CollisionKind::Exact,
+                    sources: ("chapters/intro.tex".into(), "chapters__intro.tex".into()),
+                    names: ("chapters__intro.tex".into(), "chapters__intro.tex".into()),
+                },
+                Collision {
+                    kind: CollisionKind::Case,
+                    sources: ("Figures/plot.pdf".into(), "figures/plot.pdf".into()),
+                    names: ("Figures__plot.pdf".into(), "figures__plot.pdf".into()),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::Path,
+};
+use walkdir::WalkDir;
+
+/// The name of the directory of the git repository, which is never touched.
+pub const GIT_DIRECTORY: &str = ".git";
+
+/// The patterns of the `.gitignore` written into the output, for the files of a LaTeX build.
+const IGNORED_BUILD_FILES: [&str; 10] = [
+    "*.aux",
+    "*.bbl",
+    "*.blg",
+    "*.fdb_latexmk",
+    "*.fls",
+    "*.log",
+    "*.out",
+    "*.synctex.gz",
+    "*.toc",
+    "*.xdv",
+];
+
+/// The digests of the files of an output directory before it is written,
+/// keyed by their `/`-separated path. The git directory is skipped.
+pub fn snapshot(root: &Path) -> io::Result<BTreeMap<String, Vec<u8>>> {
+    let mut files = BTreeMap::new();
+
+    for entry in WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|e| e.depth() != 1 || e.file_name() != GIT_DIRECTORY)
+    {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let name = entry
+            .path()
+            .strip_prefix(root)
+            .unwrap()
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let mut hasher = Sha256::new();
+        io::copy(&mut fs::File::open(entry.path())?, &mut hasher)?;
+        files.insert(name, hasher.finalize().to_vec());
+    }
+
+    Ok(files)
+}
+
+/// The files of the output directory which were added or changed by a run.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Changes {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+/// Compare the snapshots of the output directory before and after a run.
+pub fn changes(before: &BTreeMap<String, Vec<u8>>, after: &BTreeMap<String, Vec<u8>>) -> Changes {
+    let mut changes = Changes::default();
+
+    for (name, digest) in after {
+        match before.get(name) {
+            None => changes.added.push(name.clone()),
+            Some(previous) if previous != digest => changes.changed.push(name.clone()),
+            Some(_) => {}
+        }
+    }
+
+    changes
+}
+
+/// The `.gitattributes` marking the files with the given extensions as binary, sorted.
+pub fn gitattributes(binary_extensions: &BTreeSet<String>) -> String {
+    binary_extensions
+        .iter()
+        .map(|extension| format!("*.{extension} binary\n"))
+        .collect()
+}
+
+/// The `.gitignore` of the files of a LaTeX build.
+pub fn gitignore() -> String {
+    IGNORED_BUILD_FILES
+        .iter()
+        .map(|pattern| format!("{pattern}\n"))
+        .collect()
+}
+
+/// Determine if the content is binary, like git does: if it has a NUL byte near its start.
+pub fn is_binary(content: &[u8]) -> bool {
+    content.iter().take(8000).any(|byte| *byte == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(GIT_DIRECTORY)).unwrap();
+        fs::write(dir.path().join(".git/HEAD"), "ref: refs/heads/main").unwrap();
+        fs::write(dir.path().join("main.tex"), "old").unwrap();
+        fs::write(dir.path().join("plot.pdf"), "plot").unwrap();
+
+        let before = snapshot(dir.path()).unwrap();
+        assert_eq!(
+            before.keys().collect::<Vec<_>>(),
+            vec!["main.tex", "plot.pdf"]
+        );
+
+        fs::write(dir.path().join("main.tex"), "new").unwrap();
+        fs::write(dir.path().join("intro.tex"), "intro").unwrap();
+        let after = snapshot(dir.path()).unwrap();
+
+        assert_eq!(
+            changes(&before, &after),
+            Changes {
+                added: vec!["intro.tex".to_string()],
+                changed: vec!["main.tex".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_gitattributes() {
+        let extensions = BTreeSet::from(["png".to_string(), "pdf".to_string()]);
+        assert_eq!(gitattributes(&extensions), "*.pdf binary\n*.png binary\n");
+        assert!(is_binary(b"%PDF\0"));
+        assert!(!is_binary(b"\\documentclass{article}"));
+    }
+}
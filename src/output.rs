@@ -0,0 +1,360 @@
+use crc32fast::Hasher;
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use thiserror::Error;
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CompressionError {
+    #[error("Invalid compression level {0:?}, expected 0 to 9 or store")]
+    InvalidLevel(String),
+}
+
+/// How the files of a zip archive are compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Store the files without compression.
+    Store,
+    /// Deflate the files with the given level, from 0 (fastest) to 9 (smallest).
+    Level(u8),
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::Level(6)
+    }
+}
+
+impl Compression {
+    pub fn parse(level: &str) -> Result<Self, CompressionError> {
+        if level.eq_ignore_ascii_case("store") {
+            return Ok(Compression::Store);
+        }
+
+        match level.parse() {
+            Ok(level @ 0..=9) => Ok(Compression::Level(level)),
+            _ => Err(CompressionError::InvalidLevel(level.to_string())),
+        }
+    }
+
+    fn file_options(self) -> FileOptions {
+        match self {
+            Compression::Store => {
+                FileOptions::default().compression_method(CompressionMethod::Stored)
+            }
+            Compression::Level(level) => FileOptions::default()
+                .compression_method(CompressionMethod::Deflated)
+                .compression_level(Some(level.into())),
+        }
+    }
+}
+
+/// Where the files of the new project are written to.
+pub enum Output {
+    Directory(PathBuf),
+    Zip {
+        writer: Box<Mutex<ZipWriter<File>>>,
+        options: FileOptions,
+        /// The existing archive which is replaced, with `--update-archive`.
+        update: Option<Box<Mutex<Update>>>,
+    },
+}
+
+/// An existing zip archive which is updated by writing a new one next to it.
+///
+/// The entries whose content is unchanged are copied without recompressing them,
+/// and the entries which are not written again are left out.
+pub struct Update {
+    previous: ZipArchive<File>,
+    path: PathBuf,
+    /// The number of files written to the new archive.
+    files: usize,
+    /// The entries of the previous archive which were written again.
+    written: BTreeSet<String>,
+    /// The number of entries copied unchanged from the previous archive.
+    unchanged: usize,
+}
+
+/// How the entries of an updated archive changed.
+#[derive(Debug, PartialEq, Eq)]
+pub struct UpdateSummary {
+    pub unchanged: usize,
+    /// The entries which are new or whose content changed.
+    pub rewritten: usize,
+    /// The entries whose sources disappeared.
+    pub removed: usize,
+}
+
+/// The path the updated archive is written to, before it replaces the archive at the path.
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    path.with_file_name(name)
+}
+
+/// The CRC-32 checksum of the content, as stored in zip archives.
+fn checksum(mut reader: impl Read, buffer_size: usize) -> io::Result<(u32, u64)> {
+    let mut hasher = Hasher::new();
+    let mut buffer = vec![0; buffer_size];
+    let mut size = 0;
+
+    loop {
+        match reader.read(&mut buffer)? {
+            0 => return Ok((hasher.finalize(), size)),
+            read => {
+                hasher.update(&buffer[..read]);
+                size += read as u64;
+            }
+        }
+    }
+}
+
+impl Output {
+    /// Write into the existing directory at the given path.
+    pub fn directory(path: &Path) -> Self {
+        Output::Directory(path.to_path_buf())
+    }
+
+    /// Create a new zip archive at the given path.
+    pub fn zip(path: &Path, compression: Compression) -> io::Result<Self> {
+        Ok(Output::Zip {
+            writer: Box::new(Mutex::new(ZipWriter::new(File::create(path)?))),
+            options: compression.file_options(),
+            update: None,
+        })
+    }
+
+    /// Update the existing zip archive at the given path.
+    ///
+    /// The new archive is written to the [`partial_path`],
+    /// and replaces the existing one when the output is finished.
+    pub fn update_zip(path: &Path, compression: Compression) -> io::Result<Self> {
+        let previous = ZipArchive::new(File::open(path)?)?;
+
+        Ok(Output::Zip {
+            writer: Box::new(Mutex::new(ZipWriter::new(File::create(partial_path(
+                path,
+            ))?))),
+            options: compression.file_options(),
+            update: Some(Box::new(Mutex::new(Update {
+                previous,
+                path: path.to_path_buf(),
+                files: 0,
+                written: BTreeSet::new(),
+                unchanged: 0,
+            }))),
+        })
+    }
+
+    /// Write a file with the given `/`-separated name, creating the parent directories.
+    pub fn write(&self, name: &str, content: &[u8]) -> io::Result<()> {
+        match self {
+            Output::Directory(root) => {
+                let path = root.join(name);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(path, content)
+            }
+            Output::Zip {
+                writer,
+                options,
+                update,
+            } => {
+                let mut writer = writer.lock().unwrap();
+                if let Some(update) = update {
+                    let crc32 = crc32fast::hash(content);
+                    let size = content.len() as u64;
+                    if update
+                        .lock()
+                        .unwrap()
+                        .copy_unchanged(name, crc32, size, &mut writer)?
+                    {
+                        return Ok(());
+                    }
+                }
+                writer.start_file(name, *options)?;
+                writer.write_all(content)
+            }
+        }
+    }
+
+    /// Stream the content of the reader into a file with the given `/`-separated name,
+    /// through a buffer of the given size, so large files are not held in memory.
+    ///
+    /// When updating an archive, the content is read twice if it changed.
+    pub fn copy(
+        &self,
+        name: &str,
+        mut reader: impl Read + Seek,
+        buffer_size: usize,
+    ) -> io::Result<()> {
+        if let Output::Zip {
+            writer,
+            update: Some(update),
+            ..
+        } = self
+        {
+            let (crc32, size) = checksum(&mut reader, buffer_size)?;
+            let mut writer = writer.lock().unwrap();
+            if update
+                .lock()
+                .unwrap()
+                .copy_unchanged(name, crc32, size, &mut writer)?
+            {
+                return Ok(());
+            }
+            reader.seek(SeekFrom::Start(0))?;
+        }
+        let mut reader = BufReader::with_capacity(buffer_size, reader);
+
+        match self {
+            Output::Directory(root) => {
+                let path = root.join(name);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                io::copy(&mut reader, &mut File::create(path)?)?;
+            }
+            Output::Zip {
+                writer, options, ..
+            } => {
+                let mut writer = writer.lock().unwrap();
+                writer.start_file(name, *options)?;
+                io::copy(&mut reader, &mut *writer)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Complete the output, writing the central directory of zip archives.
+    ///
+    /// An updated archive replaces the previous one, and the summary of its changes is returned.
+    pub fn finish(self) -> io::Result<Option<UpdateSummary>> {
+        match self {
+            Output::Directory(_) => Ok(None),
+            Output::Zip { writer, update, .. } => {
+                writer.into_inner().unwrap().finish()?;
+                let Some(update) = update else {
+                    return Ok(None);
+                };
+
+                let update = update.into_inner().unwrap();
+                fs::rename(partial_path(&update.path), &update.path)?;
+                Ok(Some(UpdateSummary {
+                    unchanged: update.unchanged,
+                    rewritten: update.files - update.unchanged,
+                    removed: update.previous.len() - update.written.len(),
+                }))
+            }
+        }
+    }
+}
+
+impl Update {
+    /// Copy the entry of the previous archive with the given name into the writer
+    /// if its content has the given checksum and size, returning whether it was copied.
+    ///
+    /// Must be called for every file written to the new archive.
+    fn copy_unchanged(
+        &mut self,
+        name: &str,
+        crc32: u32,
+        size: u64,
+        writer: &mut ZipWriter<File>,
+    ) -> io::Result<bool> {
+        self.files += 1;
+        let Ok(entry) = self.previous.by_name(name) else {
+            return Ok(false);
+        };
+        self.written.insert(name.to_string());
+        if entry.crc32() != crc32 || entry.size() != size {
+            return Ok(false);
+        }
+
+        writer.raw_copy_file(entry)?;
+        self.unchanged += 1;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = Output::directory(dir.path());
+        let content: Vec<_> = (0..=255).cycle().take(10_000).collect();
+
+        output
+            .copy("a/b.bin", io::Cursor::new(&content), 64)
+            .unwrap();
+        assert_eq!(fs::read(dir.path().join("a/b.bin")).unwrap(), content);
+    }
+
+    #[test]
+    fn test_update_zip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.zip");
+        let output = Output::zip(&path, Compression::default()).unwrap();
+        for (name, content) in [
+            ("same.tex", "same"),
+            ("changed.tex", "old"),
+            ("gone.tex", ""),
+        ] {
+            output.write(name, content.as_bytes()).unwrap();
+        }
+        assert_eq!(output.finish().unwrap(), None);
+
+        let output = Output::update_zip(&path, Compression::Store).unwrap();
+        output.write("same.tex", b"same").unwrap();
+        output
+            .copy("changed.tex", io::Cursor::new(b"new"), 64)
+            .unwrap();
+        output.write("added.tex", b"added").unwrap();
+        assert_eq!(
+            output.finish().unwrap(),
+            Some(UpdateSummary {
+                unchanged: 1,
+                rewritten: 2,
+                removed: 1,
+            })
+        );
+
+        let mut archive = ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let mut names: Vec<_> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(names, vec!["added.tex", "changed.tex", "same.tex"]);
+        let mut content = String::new();
+        archive
+            .by_name("changed.tex")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "new");
+        assert!(!partial_path(&path).exists());
+    }
+
+    #[test]
+    fn test_parse_compression() {
+        assert_eq!(Compression::parse("store"), Ok(Compression::Store));
+        assert_eq!(Compression::parse("0"), Ok(Compression::Level(0)));
+        assert_eq!(Compression::parse("9"), Ok(Compression::Level(9)));
+        assert_eq!(
+            Compression::parse("10"),
+            Err(CompressionError::InvalidLevel("10".to_string()))
+        );
+        assert_eq!(
+            Compression::parse("best"),
+            Err(CompressionError::InvalidLevel("best".to_string()))
+        );
+    }
+}
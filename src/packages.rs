@@ -0,0 +1,119 @@
+/// Common packages of TeX distributions, which are never copied from the project.
+pub const SYSTEM_PACKAGES: [&str; 96] = [
+    "acro",
+    "algorithm",
+    "algorithmic",
+    "algorithm2e",
+    "algpseudocode",
+    "amsfonts",
+    "amsmath",
+    "amssymb",
+    "amsthm",
+    "appendix",
+    "array",
+    "babel",
+    "biblatex",
+    "booktabs",
+    "calc",
+    "caption",
+    "cite",
+    "cleveref",
+    "color",
+    "colortbl",
+    "csquotes",
+    "datetime",
+    "enumerate",
+    "enumitem",
+    "etoolbox",
+    "fancyhdr",
+    "float",
+    "fontenc",
+    "fontspec",
+    "footmisc",
+    "geometry",
+    "glossaries",
+    "graphics",
+    "graphicx",
+    "hyperref",
+    "ifthen",
+    "import",
+    "inputenc",
+    "kvoptions",
+    "lastpage",
+    "latexsym",
+    "lineno",
+    "listings",
+    "lmodern",
+    "longtable",
+    "makecell",
+    "makeidx",
+    "mathpazo",
+    "mathptmx",
+    "mathtools",
+    "mdframed",
+    "microtype",
+    "minted",
+    "multibib",
+    "multicol",
+    "multirow",
+    "natbib",
+    "nomencl",
+    "paralist",
+    "parskip",
+    "pdflscape",
+    "pdfpages",
+    "pgf",
+    "pgfplots",
+    "placeins",
+    "setspace",
+    "siunitx",
+    "soul",
+    "standalone",
+    "subcaption",
+    "subfig",
+    "subfiles",
+    "tabularx",
+    "tcolorbox",
+    "textcomp",
+    "threeparttable",
+    "tikz",
+    "times",
+    "titlesec",
+    "tocbibind",
+    "todonotes",
+    "txfonts",
+    "ulem",
+    "url",
+    "verbatim",
+    "wrapfig",
+    "xargs",
+    "xcolor",
+    "xparse",
+    "xspace",
+    "xstring",
+    "xurl",
+    "fancyvrb",
+    "upgreek",
+    "bm",
+    "chngcntr",
+];
+
+/// Determine if the package is part of the TeX distribution,
+/// either built-in or declared as such in the config file.
+pub fn is_system_package(name: &str, configured: &[String]) -> bool {
+    SYSTEM_PACKAGES.contains(&name) || configured.iter().any(|system| system == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_system_package() {
+        let configured = vec!["acmart-extras".to_string()];
+
+        assert!(is_system_package("amsmath", &configured));
+        assert!(is_system_package("acmart-extras", &configured));
+        assert!(!is_system_package("mystyle", &configured));
+    }
+}
@@ -0,0 +1,81 @@
+use std::io::{self, BufRead, Write};
+
+/// Ask a yes/no question, defaulting to no.
+///
+/// Returns true if the answer is `y` or `yes`, ignoring case.
+pub fn confirm(
+    question: &str,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<bool> {
+    write!(output, "{question} [y/N] ")?;
+    output.flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    let answer = answer.trim();
+
+    Ok(answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"))
+}
+
+/// Ask for a value, offering a default which is taken if the answer is empty.
+///
+/// Returns the trimmed answer, or the default.
+pub fn ask(
+    question: &str,
+    default: &str,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<String> {
+    write!(output, "{question} [{default}] ")?;
+    output.flush()?;
+
+    let mut answer = String::new();
+    if input.read_line(&mut answer)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let answer = answer.trim();
+
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confirm() {
+        for (answer, expected) in [
+            ("y\n", true),
+            ("YES\n", true),
+            ("n\n", false),
+            ("\n", false),
+            ("", false),
+        ] {
+            let mut output = Vec::new();
+            assert_eq!(
+                confirm("Delete?", answer.as_bytes(), &mut output).unwrap(),
+                expected
+            );
+            assert_eq!(output, b"Delete? [y/N] ");
+        }
+    }
+
+    #[test]
+    fn test_ask() {
+        for (answer, expected) in [
+            ("intro.tex\n", "intro.tex"),
+            ("  intro.tex  \n", "intro.tex"),
+            ("\n", "main-1a2b.tex"),
+        ] {
+            let mut output = Vec::new();
+            assert_eq!(
+                ask("Name?", "main-1a2b.tex", answer.as_bytes(), &mut output).unwrap(),
+                expected
+            );
+            assert_eq!(output, b"Name? [main-1a2b.tex] ");
+        }
+
+        assert!(ask("Name?", "main.tex", "".as_bytes(), Vec::new()).is_err());
+    }
+}
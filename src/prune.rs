@@ -0,0 +1,84 @@
+use crate::commands::{BIBLIOGRAPHY_EXTENSIONS, GRAPHICS_EXTENSIONS, TEX_EXTENSIONS};
+use std::{collections::BTreeSet, fs, io, path::Path};
+
+/// The directories of version control systems, which are never touched.
+pub const VCS_DIRECTORIES: [&str; 3] = [".git", ".hg", ".svn"];
+
+/// Determine if the project is under version control, so deleted files can be restored.
+pub fn is_version_controlled(root: &Path) -> bool {
+    let Ok(root) = fs::canonicalize(root) else {
+        return false;
+    };
+
+    root.ancestors()
+        .any(|dir| VCS_DIRECTORIES.iter().any(|vcs| dir.join(vcs).exists()))
+}
+
+/// Determine if the file is of a kind that include commands reference.
+///
+/// Other files, like local packages, build scripts or READMEs, can be needed without being
+/// referenced by an include command, so they are never pruned.
+pub fn is_prunable(source: &str) -> bool {
+    Path::new(source)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            TEX_EXTENSIONS
+                .iter()
+                .chain(&GRAPHICS_EXTENSIONS)
+                .chain(&BIBLIOGRAPHY_EXTENSIONS)
+                .any(|prunable| ext.eq_ignore_ascii_case(prunable))
+        })
+}
+
+/// The prunable files of the project which are not referenced.
+pub fn unreferenced_files(
+    sources: impl IntoIterator<Item = String>,
+    referenced: &BTreeSet<String>,
+) -> Vec<String> {
+    sources
+        .into_iter()
+        .filter(|source| !referenced.contains(source) && is_prunable(source))
+        .collect()
+}
+
+/// Delete the given files of the project, as well as directories that become empty.
+pub fn prune_in_place(root: &Path, files: &[String]) -> io::Result<()> {
+    for source in files {
+        let path = root.join(source);
+        fs::remove_file(&path)?;
+
+        // Clean up the directories which are now empty
+        for dir in path.ancestors().skip(1) {
+            if dir == root || fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_prunable() {
+        assert!(is_prunable("content/unused.tex"));
+        assert!(is_prunable("figures/old.PNG"));
+        assert!(!is_prunable("styles/thesis.sty"));
+        assert!(!is_prunable("Makefile"));
+    }
+
+    #[test]
+    fn test_unreferenced_files() {
+        let sources = ["main.tex", "unused.tex", "figures/old.png", "thesis.sty"];
+        let referenced = BTreeSet::from(["main.tex".to_string()]);
+
+        assert_eq!(
+            unreferenced_files(sources.map(str::to_string), &referenced),
+            vec!["unused.tex", "figures/old.png"]
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use crate::{
+    diagnostic::{self, Code, Diagnostic, Severity},
+    engine::{Engine, EngineHint},
+    stats::DirectoryStats,
+};
+use serde::Serialize;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::{self, Write},
+    sync::Mutex,
+};
+
+/// A file of the project and where it ended up.
+#[derive(Debug, Serialize)]
+pub struct FileEntry {
+    /// The path relative to the project root.
+    pub source: String,
+    /// The flattened output name.
+    pub output: String,
+    /// False if the content was already written under the output name of another file.
+    pub copied: bool,
+    /// The hexadecimal SHA-256 digest of the written content, with `--manifest-hashes`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// A reference of an include command to a project file.
+#[derive(Debug)]
+pub struct ImportEntry {
+    /// The file containing the command, relative to the project root.
+    pub from: String,
+    /// The referenced project file, or empty if the reference doesn't resolve.
+    pub to: String,
+    /// The name of the command, without the backslash.
+    pub command: String,
+    /// The path argument after flattening.
+    pub rewritten: String,
+}
+
+/// The structured result of a run.
+#[derive(Debug, Default, Serialize)]
+struct ReportData {
+    files: Vec<FileEntry>,
+    diagnostics: Vec<Diagnostic>,
+    /// The files and bytes per top-level directory, with `--stats`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stats: Vec<DirectoryStats>,
+    /// The engine the project needs, inferred from the engine hints.
+    engine: Engine,
+    /// The packages and primitives which require a specific engine.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    engine_hints: Vec<EngineHint>,
+    #[serde(skip)]
+    imports: Vec<ImportEntry>,
+}
+
+/// Collects the outcome of a run, to be printed at the end.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Don't print warnings when they are emitted.
+    quiet: bool,
+    data: Mutex<ReportData>,
+}
+
+impl Report {
+    pub fn new(quiet: bool) -> Self {
+        Self {
+            quiet,
+            ..Default::default()
+        }
+    }
+
+    /// Emit a diagnostic, printing it to stderr unless the report is quiet.
+    /// Errors are printed even if it is.
+    pub fn emit(&self, diagnostic: Diagnostic) {
+        diagnostic::print(&diagnostic, self.quiet);
+        self.data.lock().unwrap().diagnostics.push(diagnostic);
+    }
+
+    /// The number of emitted warnings.
+    pub fn warning_count(&self) -> usize {
+        let data = self.data.lock().unwrap();
+        data.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Warning)
+            .count()
+    }
+
+    /// The number of emitted errors.
+    pub fn error_count(&self) -> usize {
+        let data = self.data.lock().unwrap();
+        data.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .count()
+    }
+
+    pub fn add_file(&self, file: FileEntry) {
+        self.data.lock().unwrap().files.push(file);
+    }
+
+    pub fn add_import(&self, import: ImportEntry) {
+        self.data.lock().unwrap().imports.push(import);
+    }
+
+    pub fn set_stats(&self, stats: Vec<DirectoryStats>) {
+        self.data.lock().unwrap().stats = stats;
+    }
+
+    /// Add a feature requiring a specific engine,
+    /// warning if it conflicts with the engine of the features added before.
+    pub fn add_engine_hint(&self, hint: EngineHint) {
+        let mut data = self.data.lock().unwrap();
+        let engine = data.engine.combine(hint.engine);
+        let conflict =
+            (engine == Engine::Conflicting && data.engine != Engine::Conflicting).then(|| {
+                Diagnostic::warning(
+                    Code::EngineConflict,
+                    format!(
+                        "{} requires {}, but the project also uses features requiring {}",
+                        hint.feature,
+                        hint.engine.name(),
+                        data.engine.name()
+                    ),
+                )
+                .at(&hint.source, hint.line)
+            });
+        data.engine = engine;
+        data.engine_hints.push(hint);
+        drop(data);
+
+        if let Some(conflict) = conflict {
+            self.emit(conflict);
+        }
+    }
+
+    /// The engine the project needs, inferred from the engine hints.
+    pub fn engine(&self) -> Engine {
+        self.data.lock().unwrap().engine
+    }
+
+    /// Write the include commands as CSV with a header row, sorted by the containing file.
+    ///
+    /// With `hashes`, the rows have the digest of the output file of the referenced file.
+    pub fn write_csv(&self, mut writer: impl Write, hashes: bool) -> io::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        data.imports.sort_by(|a, b| a.from.cmp(&b.from));
+
+        // Duplicates have the digest of the file written under their output name
+        let digests: HashMap<_, _> = data
+            .files
+            .iter()
+            .filter_map(|file| Some((file.output.as_str(), file.sha256.as_deref()?)))
+            .collect();
+        let outputs: HashMap<_, _> = data
+            .files
+            .iter()
+            .map(|file| (file.source.as_str(), file.output.as_str()))
+            .collect();
+
+        write!(writer, "from,to,command,rewritten")?;
+        writeln!(writer, "{}", if hashes { ",sha256" } else { "" })?;
+        for import in &data.imports {
+            let mut fields = vec![
+                import.from.as_str(),
+                &import.to,
+                &import.command,
+                &import.rewritten,
+            ];
+            if hashes {
+                let output = outputs.get(import.to.as_str());
+                fields.push(output.and_then(|output| digests.get(output)).unwrap_or(&""));
+            }
+            let row: Vec<_> = fields.iter().map(|field| csv_field(field)).collect();
+            writeln!(writer, "{}", row.join(","))?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the report as JSON, with the files sorted by source path.
+    pub fn write_json(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        data.files.sort_by(|a, b| a.source.cmp(&b.source));
+
+        serde_json::to_writer_pretty(&mut writer, &*data)?;
+        writeln!(writer)
+    }
+}
+
+/// Quote the field per RFC 4180 if it contains a comma, quote or line break.
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostics() {
+        let report = Report::new(true);
+        report.emit(
+            Diagnostic::warning(Code::InvalidUtf8, "Not valid UTF-8".to_string())
+                .in_file("data.tex"),
+        );
+        report.emit(Diagnostic::error(
+            Code::ExactCollision,
+            "\"a/b.tex\" and \"a__b.tex\" are both flattened to \"a__b.tex\"".to_string(),
+        ));
+        assert_eq!(report.warning_count(), 1);
+
+        let mut json = Vec::new();
+        report.write_json(&mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["diagnostics"][0]["code"], "LF0006");
+        assert_eq!(json["diagnostics"][0]["file"], "data.tex");
+        assert_eq!(json["diagnostics"][1]["severity"], "error");
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let report = Report::new(true);
+        report.add_import(ImportEntry {
+            from: "main.tex".to_string(),
+            to: "refs.bib".to_string(),
+            command: "bibliography".to_string(),
+            rewritten: "refs,other".to_string(),
+        });
+        report.add_import(ImportEntry {
+            from: "chapters/a.tex".to_string(),
+            to: String::new(),
+            command: "input".to_string(),
+            rewritten: r#""my file""#.to_string(),
+        });
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv, false).unwrap();
+
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "from,to,command,rewritten\n\
+             chapters/a.tex,,input,\"\"\"my file\"\"\"\n\
+             main.tex,refs.bib,bibliography,\"refs,other\"\n"
+        );
+    }
+
+    #[test]
+    fn test_write_csv_hashes() {
+        let report = Report::new(true);
+        for (source, sha256) in [("fig/a.pdf", Some("ab12")), ("fig2/a.pdf", None)] {
+            report.add_file(FileEntry {
+                source: source.to_string(),
+                output: "a.pdf".to_string(),
+                copied: sha256.is_some(),
+                sha256: sha256.map(str::to_string),
+            });
+        }
+        for to in ["fig2/a.pdf", "missing.pdf"] {
+            report.add_import(ImportEntry {
+                from: "main.tex".to_string(),
+                to: to.to_string(),
+                command: "includegraphics".to_string(),
+                rewritten: "a".to_string(),
+            });
+        }
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv, true).unwrap();
+
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "from,to,command,rewritten,sha256\n\
+             main.tex,fig2/a.pdf,includegraphics,a,ab12\n\
+             main.tex,missing.pdf,includegraphics,a,\n"
+        );
+    }
+}
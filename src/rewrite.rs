@@ -0,0 +1,1430 @@
+use crate::{
+    commands::{CommandKind, BIBLIOGRAPHY_EXTENSIONS, GRAPHICS_EXTENSIONS, TEX_EXTENSIONS},
+    deps::{strip_comment, strip_last_comment},
+    mapping::{is_outside_root, Mapping},
+    packages::is_system_package,
+    scope::Scope,
+    Args, ReferenceExtension,
+};
+use regex::{Captures, Regex};
+use std::{borrow::Cow, path::Path, sync::LazyLock};
+
+/// An include command matched while rewriting a line.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Import {
+    /// The name of the command, without the backslash.
+    pub command: String,
+    /// The path argument as written in the source.
+    pub original: String,
+    /// The path argument after flattening.
+    pub rewritten: String,
+    /// The project files the argument resolved to.
+    pub targets: Vec<String>,
+    /// The references whose stated extension doesn't exist,
+    /// with the project file that exists with another extension.
+    pub mismatched_extensions: Vec<(String, String)>,
+    /// The references which are absolute or escape the project root,
+    /// and are not included with `--include-external-refs`.
+    pub outside_root: Vec<String>,
+    /// The references which don't resolve to a project file.
+    pub unresolved: Vec<String>,
+    /// The macros in the path argument which are not defined in the file.
+    pub undefined_macros: Vec<String>,
+    /// The macros in the path argument which expand to an absolute path.
+    pub absolute_macros: Vec<String>,
+    /// Why the path argument is left unchanged, if it is skipped.
+    pub skipped: Option<SkipReason>,
+}
+
+impl Import {
+    /// A command whose path argument is left unchanged for the given reason.
+    pub fn skipped(command: &str, original: &str, reason: SkipReason) -> Self {
+        Self {
+            command: command.to_string(),
+            original: original.to_string(),
+            rewritten: original.to_string(),
+            targets: Vec::new(),
+            mismatched_extensions: Vec::new(),
+            outside_root: Vec::new(),
+            unresolved: Vec::new(),
+            undefined_macros: Vec::new(),
+            absolute_macros: Vec::new(),
+            skipped: Some(reason),
+        }
+    }
+}
+
+/// Why an include command is not rewritten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The command is excluded with `--preserve-command`.
+    Preserved,
+    /// The path argument has a protective wrapper which can't be removed.
+    Protected,
+    /// The command is in a block disabled with `\iffalse`.
+    Disabled,
+    /// The command is in a verbatim environment.
+    Verbatim,
+    /// The command is in a code block of a document which is not LaTeX.
+    Code,
+    /// The command is in a comment.
+    Commented,
+}
+
+impl SkipReason {
+    /// The reason, as printed by `--explain`.
+    pub fn name(self) -> &'static str {
+        match self {
+            SkipReason::Preserved => "preserved",
+            SkipReason::Protected => "protected",
+            SkipReason::Disabled => "disabled",
+            SkipReason::Verbatim => "verbatim",
+            SkipReason::Code => "code",
+            SkipReason::Commented => "commented",
+        }
+    }
+}
+
+/// The wrappers which protect a path argument from expansion, see [`unwrap_path`].
+const WRAPPERS: [&str; 4] = [r"\protect", r"\string", r"\detokenize", r"\unexpanded"];
+
+/// The wrappers which can enclose the whole path argument in a group.
+const GROUP_WRAPPERS: [&str; 2] = [r"\detokenize{", r"\unexpanded{"];
+
+/// A command with a single braced argument, like `\myfig{figures/x}`.
+static SINGLE_ARGUMENT_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\\([A-Za-z@]+)\*?[ \t]*\{([^{}]*)\}").unwrap());
+
+/// A link command with its target, like `\href{docs/appendix.pdf}` or `\url{https://example.com}`.
+static LINK_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\\(?:href|url)[ \t]*\{([^{}]*)\}").unwrap());
+
+/// A URL scheme, like `https:` or `mailto:`.
+static SCHEME_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z][A-Za-z0-9+.-]*:").unwrap());
+
+/// Rewrite the imports of the line, reporting every matched command to `on_import`.
+///
+/// The commands in the comment at the end of the line are reported as skipped.
+pub fn trace_imports<'a>(
+    line: &'a str,
+    args: &Args,
+    mapping: &Mapping,
+    scope: &Scope,
+    mut on_import: impl FnMut(Import),
+) -> Cow<'a, str> {
+    let mut rewrite_import = |capture: &Captures| -> String {
+        let whole = capture.get(0).unwrap();
+        // Command type
+        let command = capture.get(1).map_or("input", |mat| mat.as_str());
+        let path = capture.get(3).or(capture.get(4)).unwrap();
+        let original = path.as_str();
+        // Preserved commands win over every rewrite rule
+        if args.preserve_command.iter().any(|name| name == command) {
+            on_import(Import::skipped(command, original, SkipReason::Preserved));
+            return whole.as_str().to_string();
+        }
+        // Flatten the paths
+        let uncommented = strip_path_comments(original);
+        let unwrapped = unwrap_path(&uncommented);
+        let normalized = normalize_separators(&unwrapped.path);
+        // Paths with spaces can be quoted, e.g. `\includegraphics{"my plot.pdf"}`
+        let unquoted = normalized
+            .strip_prefix('"')
+            .and_then(|path| path.strip_suffix('"'));
+        let normalized = unquoted.unwrap_or(&normalized);
+        let absolute_macros = scope.macros.absolute_macros(normalized);
+        let (normalized, undefined_macros) = scope.macros.expand(normalized);
+
+        // A macro behind a wrapper can't be resolved, and flattening it would corrupt the path
+        if unwrapped.wrapped && has_control_word(&normalized) {
+            on_import(Import {
+                absolute_macros,
+                ..Import::skipped(command, original, SkipReason::Protected)
+            });
+            return whole.as_str().to_string();
+        }
+
+        let kind = args.commands.kind(command).unwrap();
+        let extensions = kind.extensions();
+        let policy = match kind {
+            CommandKind::Input => args.reference_extension,
+            CommandKind::Graphics
+            | CommandKind::Bibliography
+            | CommandKind::BibliographyStyle
+            | CommandKind::Standalone
+            | CommandKind::Package
+            | CommandKind::Class => ReferenceExtension::Keep,
+        };
+
+        // Bibliographies and packages can be given as a comma-separated list
+        let references: Vec<_> = if matches!(kind, CommandKind::Bibliography | CommandKind::Package)
+        {
+            normalized.split(',').collect()
+        } else {
+            vec![&normalized]
+        };
+
+        let mut targets = Vec::new();
+        let mut mismatched_extensions = Vec::new();
+        let mut unresolved = Vec::new();
+        let outside_root = references
+            .iter()
+            .filter(|reference| {
+                is_outside_root(reference) && mapping.resolve(reference, extensions).is_none()
+            })
+            .map(|reference| reference.trim().to_string())
+            .collect();
+        let rewritten = references
+            .iter()
+            .map(|&reference| {
+                if matches!(kind, CommandKind::Package | CommandKind::Class)
+                    && !is_local_package(reference, extensions, args, mapping)
+                {
+                    return reference.to_string();
+                }
+                // Styles of the distribution, like `plain`, are not project files
+                if kind == CommandKind::BibliographyStyle
+                    && !reference.contains('/')
+                    && mapping.resolve(reference, extensions).is_none()
+                {
+                    return reference.to_string();
+                }
+
+                // Like LaTeX, graphics are also searched in the `\graphicspath` directories
+                let searched = (kind == CommandKind::Graphics
+                    && mapping.resolve(reference, extensions).is_none())
+                .then(|| {
+                    scope
+                        .graphics_paths
+                        .iter()
+                        .map(|directory| format!("{directory}{}", reference.trim()))
+                        .find(|path| mapping.resolve(path, extensions).is_some())
+                })
+                .flatten();
+                let reference = searched.as_deref().unwrap_or(reference);
+
+                let available = (kind == CommandKind::Graphics)
+                    .then(|| mapping.resolve_other_extension(reference, extensions))
+                    .flatten();
+                let reference = match available {
+                    Some(available) => {
+                        mismatched_extensions
+                            .push((reference.trim().to_string(), available.to_string()));
+                        if args.fix_extensions {
+                            available
+                        } else {
+                            reference
+                        }
+                    }
+                    None => reference,
+                };
+
+                match mapping.resolve(reference, extensions) {
+                    Some(target) => targets.push(target.to_string()),
+                    // Macro parameters like `#1` are only known where the macro is used
+                    None if reference.contains('#') => {}
+                    None => unresolved.push(reference.trim().to_string()),
+                }
+                if kind == CommandKind::Standalone {
+                    // The precompiled figure, which is used instead of the source in image mode
+                    targets.extend(mapping.resolve(reference, &["pdf"]).map(str::to_string));
+                }
+                // Keep the spacing around the items of lists
+                let start = reference.len() - reference.trim_start().len();
+                let end = reference.trim_end().len();
+                format!(
+                    "{}{}{}",
+                    &reference[..start],
+                    mapping.flatten_reference(&reference[start..end], extensions, policy),
+                    &reference[end..]
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let rewritten = if unquoted.is_some() && rewritten.contains(' ') {
+            format!("\"{rewritten}\"")
+        } else {
+            rewritten
+        };
+        let rewritten = match unwrapped.group {
+            Some(group) => format!("{group}{rewritten}}}"),
+            None => rewritten,
+        };
+
+        // Only replace the path, keeping the other arguments and spacing as written
+        let replacement = format!(
+            "{}{rewritten}{}",
+            &whole.as_str()[..path.start() - whole.start()],
+            &whole.as_str()[path.end() - whole.start()..]
+        );
+        on_import(Import {
+            command: command.to_string(),
+            original: original.to_string(),
+            rewritten,
+            targets,
+            mismatched_extensions,
+            outside_root,
+            unresolved,
+            undefined_macros,
+            absolute_macros,
+            skipped: None,
+        });
+        replacement
+    };
+
+    let code = strip_last_comment(line);
+    let mut rewritten = Cow::Borrowed(code);
+    for regex in args.commands.import_regexes() {
+        let replaced = match regex.replace_all(&rewritten, &mut rewrite_import) {
+            Cow::Owned(replaced) => Some(replaced),
+            Cow::Borrowed(_) => None,
+        };
+        if let Some(replaced) = replaced {
+            rewritten = Cow::Owned(replaced);
+        }
+    }
+
+    let comment = &line[code.len()..];
+    for regex in args.commands.import_regexes() {
+        for capture in regex.captures_iter(comment) {
+            let command = capture.get(1).map_or("input", |mat| mat.as_str());
+            let path = capture.get(3).or(capture.get(4)).unwrap();
+            on_import(Import::skipped(
+                command,
+                path.as_str(),
+                SkipReason::Commented,
+            ));
+        }
+    }
+
+    match rewritten {
+        Cow::Borrowed(_) => Cow::Borrowed(line),
+        Cow::Owned(rewritten) => Cow::Owned(rewritten + comment),
+    }
+}
+
+/// A path argument with its protective wrappers removed.
+struct Unwrapped<'a> {
+    /// The wrapper enclosing the whole path, which is kept around the rewritten path.
+    group: Option<&'static str>,
+    path: Cow<'a, str>,
+    /// The path contained a wrapper.
+    wrapped: bool,
+}
+
+/// Remove the protective wrappers from a path argument where that doesn't change the path.
+///
+/// - `\detokenize{...}` and `\unexpanded{...}` around the whole path are removed,
+///   and put back around the rewritten path.
+/// - `\protect` is removed, as it only matters in moving arguments.
+/// - `\string` is removed before a single character like `\string~`, which it makes literal.
+///
+/// Other wrappers, like `\string` before a macro, are kept, so the path is left unchanged.
+fn unwrap_path(path: &str) -> Unwrapped<'_> {
+    if !WRAPPERS.iter().any(|wrapper| path.contains(wrapper)) {
+        return Unwrapped {
+            group: None,
+            path: Cow::Borrowed(path),
+            wrapped: false,
+        };
+    }
+
+    let (group, mut rest) = GROUP_WRAPPERS
+        .into_iter()
+        .find_map(|group| {
+            let inner = path.strip_prefix(group)?.strip_suffix('}')?;
+            Some((Some(group), inner))
+        })
+        .unwrap_or((None, path));
+
+    let mut unwrapped = String::with_capacity(rest.len());
+    while let Some(index) = rest.find('\\') {
+        unwrapped.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        if let Some(after) = strip_control_word(rest, r"\protect") {
+            rest = after;
+        } else if let Some(after) = strip_control_word(rest, r"\string")
+            .filter(|after| !after.is_empty() && !after.starts_with('\\'))
+        {
+            let char = after.chars().next().unwrap();
+            unwrapped.push(char);
+            rest = &after[char.len_utf8()..];
+        } else {
+            unwrapped.push('\\');
+            rest = &rest[1..];
+        }
+    }
+    unwrapped.push_str(rest);
+
+    Unwrapped {
+        group,
+        path: Cow::Owned(unwrapped),
+        wrapped: true,
+    }
+}
+
+/// Strip the control word with the given name from the start of the text,
+/// including the spaces TeX skips after it.
+fn strip_control_word<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    text.strip_prefix(name)
+        .filter(|rest| !rest.starts_with(|char: char| char.is_ascii_alphabetic()))
+        .map(|rest| rest.trim_start_matches(' '))
+}
+
+/// Determine if the text contains a control word like `\jobname`.
+fn has_control_word(text: &str) -> bool {
+    text.match_indices('\\')
+        .any(|(index, _)| text[index + 1..].starts_with(|char: char| char.is_ascii_alphabetic()))
+}
+
+/// Determine if the package or class is a `.sty` or `.cls` file of the project,
+/// rather than of the TeX distribution.
+///
+/// Packages are local if they are referenced by path or exist in the project
+/// and are not known to be distribution packages.
+fn is_local_package(reference: &str, extensions: &[&str], args: &Args, mapping: &Mapping) -> bool {
+    let name = reference.trim();
+
+    !is_system_package(name, &args.packages.system)
+        && (name.contains('/') || mapping.resolve(name, extensions).is_some())
+}
+
+/// Split the content into lines, where a line ending with an include command
+/// is joined with the following lines holding its arguments, with the 0-based index of the
+/// first line and the line ending, which is empty for the last line without one.
+///
+/// Like TeX, comments between the command and its arguments are skipped,
+/// e.g. in `\includegraphics % chosen figure` followed by `[width=1cm]{fig/plot}`.
+/// A path split by a comment is joined as well, e.g. `\input{chapters/%` followed by `intro}`.
+pub fn join_continued_commands<'a>(
+    content: &'a str,
+    args: &Args,
+) -> Vec<(usize, &'a str, &'a str)> {
+    let mut offset = 0;
+    let mut lines = content
+        .split_inclusive('\n')
+        .map(|line| {
+            let start = offset;
+            offset += line.len();
+            let line = line.strip_suffix('\n').unwrap_or(line);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            (start..start + line.len(), offset)
+        })
+        .enumerate();
+
+    let mut joined = Vec::new();
+    while let Some((index, (range, mut next_start))) = lines.next() {
+        let mut end = range.end;
+        while awaits_arguments(&content[range.start..end], args)
+            || awaits_path_continuation(&content[range.start..end], args)
+        {
+            let Some((_, (next, start))) = lines.next() else {
+                break;
+            };
+            end = next.end;
+            next_start = start;
+        }
+        joined.push((index, &content[range.start..end], &content[end..next_start]));
+    }
+    joined
+}
+
+/// Rewrite the targets of the `\href` and `\url` links of the line which are project files
+/// to their flattened names, reporting each of these files to `on_target`.
+///
+/// Local targets can have the `run:` prefix, like `\href{run:./docs/appendix.pdf}{Appendix}`,
+/// and a fragment, like `#page=2`, which are kept.
+/// Targets with another scheme, like `https:`, and targets which are not project files
+/// are left unchanged.
+pub fn flatten_links<'a>(
+    line: &'a str,
+    mapping: &Mapping,
+    mut on_target: impl FnMut(&str),
+) -> Cow<'a, str> {
+    let code = strip_comment(line);
+    let mut changed = false;
+    let rewritten = LINK_REGEX.replace_all(code, |capture: &Captures| {
+        let whole = capture.get(0).unwrap();
+        let target = capture.get(1).unwrap();
+        let (prefix, path) = match target.as_str().strip_prefix("run:") {
+            Some(path) => ("run:", path),
+            None if SCHEME_REGEX.is_match(target.as_str()) => return whole.as_str().to_string(),
+            None => ("", target.as_str()),
+        };
+        let (path, fragment) = match path.find('#') {
+            Some(index) => path.split_at(index),
+            None => (path, ""),
+        };
+
+        let Some(source) = mapping.resolve(path, &[]) else {
+            return whole.as_str().to_string();
+        };
+        on_target(source);
+        changed = true;
+
+        let target_start = target.start() - whole.start();
+        let target_end = target.end() - whole.start();
+        format!(
+            "{}{prefix}{}{fragment}{}",
+            &whole.as_str()[..target_start],
+            mapping.name(source).unwrap(),
+            &whole.as_str()[target_end..]
+        )
+    });
+
+    if !changed {
+        return Cow::Borrowed(line);
+    }
+    Cow::Owned(rewritten.into_owned() + &line[code.len()..])
+}
+
+/// Determine if the text ends with an include command outside of a comment
+/// whose arguments are not given yet.
+fn awaits_arguments(text: &str, args: &Args) -> bool {
+    args.commands
+        .import_pending_regex()
+        .find(text)
+        .is_some_and(|command| {
+            let line_start = text[..command.start()]
+                .rfind('\n')
+                .map_or(0, |index| index + 1);
+            strip_comment(&text[line_start..]).len() > command.start() - line_start
+        })
+}
+
+/// Determine if the text ends inside the path argument of an include command
+/// and the last line ends with a comment, which continues the path on the next line.
+///
+/// Paths without a closing brace which are not continued by a comment are not joined,
+/// so that they don't swallow the rest of the file.
+fn awaits_path_continuation(text: &str, args: &Args) -> bool {
+    let last_line = &text[text.rfind('\n').map_or(0, |index| index + 1)..];
+    if strip_comment(last_line).len() == last_line.len() {
+        return false;
+    }
+
+    let Some(start) = args
+        .commands
+        .import_start_regex()
+        .find_iter(text)
+        .filter(|start| {
+            let line_start = text[..start.start()]
+                .rfind('\n')
+                .map_or(0, |index| index + 1);
+            strip_comment(&text[line_start..]).len() > start.start() - line_start
+        })
+        .last()
+    else {
+        return false;
+    };
+
+    let mut depth = 1;
+    for line in text[start.end()..].split('\n') {
+        for char in strip_comment(line).chars() {
+            match char {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => {}
+            }
+
+            if depth == 0 {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+/// Remove the comments of a path argument spanning several lines, like TeX.
+///
+/// A comment also removes the line break and the indentation of the next line,
+/// so `chapters/%` followed by `  intro` is `chapters/intro`.
+fn strip_path_comments(path: &str) -> Cow<'_, str> {
+    if !path.contains('\n') {
+        return Cow::Borrowed(path);
+    }
+
+    let mut stripped = String::with_capacity(path.len());
+    let mut lines = path.split('\n').peekable();
+    while let Some(line) = lines.next() {
+        let without_comment = strip_comment(line);
+        if without_comment.len() < line.len() {
+            stripped.push_str(without_comment);
+            if let Some(next) = lines.peek_mut() {
+                *next = next.trim_start_matches([' ', '\t']);
+            }
+        } else {
+            stripped.push_str(line);
+            if lines.peek().is_some() {
+                stripped.push('\n');
+            }
+        }
+    }
+
+    Cow::Owned(stripped)
+}
+
+/// A command which is not an include command, but whose argument references a project file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnsupportedCommand<'a> {
+    /// The name of the command, without the backslash.
+    pub command: &'a str,
+    /// The argument as written in the source.
+    pub argument: &'a str,
+    /// The project file the argument resolves to.
+    pub target: String,
+}
+
+/// Find the commands of the line with a single braced argument that looks like a path,
+/// i.e. it contains a `/` and resolves to a project file, but which are not include commands.
+///
+/// These are likely custom commands which are missing from the config file.
+pub fn find_unsupported_commands<'a>(
+    line: &'a str,
+    args: &Args,
+    mapping: &Mapping,
+) -> Vec<UnsupportedCommand<'a>> {
+    let extensions: Vec<_> = TEX_EXTENSIONS
+        .iter()
+        .chain(&GRAPHICS_EXTENSIONS)
+        .chain(&BIBLIOGRAPHY_EXTENSIONS)
+        .copied()
+        .collect();
+
+    SINGLE_ARGUMENT_REGEX
+        .captures_iter(strip_comment(line))
+        .filter_map(|capture| {
+            let command = capture.get(1).unwrap().as_str();
+            let argument = capture.get(2).unwrap().as_str();
+            // The argument must be the only one, unlike in `\href{figures/x}{text}`
+            let rest = &line[capture.get(0).unwrap().end()..];
+            if args.commands.kind(command).is_some()
+                || args.preserve_command.iter().any(|name| name == command)
+                || (args.flatten_href && command == "url")
+                || !argument.contains('/')
+                || rest.trim_start().starts_with(['{', '['])
+            {
+                return None;
+            }
+
+            let target = mapping.resolve(argument, &extensions)?;
+            Some(UnsupportedCommand {
+                command,
+                argument,
+                target: target.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Find the include commands in the line whose path argument is missing the closing brace.
+pub fn find_unterminated_imports<'a>(line: &'a str, args: &Args) -> Vec<&'a str> {
+    args.commands
+        .import_start_regex()
+        .captures_iter(line)
+        .filter(|capture| !args.preserve_command.iter().any(|name| name == &capture[1]))
+        .filter(|capture| {
+            let rest = &line[capture.get(0).unwrap().end()..];
+            let mut depth = 1;
+
+            for char in rest.chars() {
+                match char {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+
+                if depth == 0 {
+                    return false;
+                }
+            }
+
+            true
+        })
+        .map(|capture| capture.get(1).unwrap().as_str())
+        .collect()
+}
+
+/// Replace Windows-style backslash separators in a path argument by forward slashes.
+///
+/// A double backslash is always a separator.
+/// A single backslash is only a separator when it follows a path segment and starts a word,
+/// e.g. `figures\plot.pdf`. Macros like `\jobname` at the start of a segment
+/// and escapes like `\_` are left untouched.
+fn normalize_separators(path: &str) -> Cow<'_, str> {
+    if !path.contains('\\') {
+        return Cow::Borrowed(path);
+    }
+
+    let mut normalized = String::with_capacity(path.len());
+    let mut chars = path.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            normalized.push(char);
+            continue;
+        }
+
+        // The drive of a Windows path like `C:\Users` is a segment as well
+        let follows_segment = normalized
+            .chars()
+            .last()
+            .is_some_and(|prev| prev.is_alphanumeric() || prev == '.' || prev == '-')
+            || (normalized.len() == 2
+                && normalized.ends_with(':')
+                && normalized.starts_with(|char: char| char.is_ascii_alphabetic()));
+
+        match chars.peek() {
+            Some('\\') => {
+                chars.next();
+                normalized.push('/');
+            }
+            Some(next) if follows_segment && next.is_ascii_alphabetic() => normalized.push('/'),
+            _ => normalized.push(char),
+        }
+    }
+
+    Cow::Owned(normalized)
+}
+
+/// Print the include commands matched in the file at the given relative path.
+pub fn explain_imports(path: &Path, imports: &[(usize, Import)]) {
+    eprintln!("{}:", path.display());
+
+    if imports.is_empty() {
+        eprintln!("  no include commands");
+    }
+
+    for (line, import) in imports {
+        eprintln!("  {}", explanation(*line, import));
+    }
+}
+
+/// Describe how the include command on the given line was rewritten, or why it wasn't.
+fn explanation(line: usize, import: &Import) -> String {
+    let decision = match import.skipped {
+        Some(reason) => format!("skipped: {}", reason.name()),
+        None if import.original == import.rewritten => "unchanged".to_string(),
+        None => "rewritten".to_string(),
+    };
+
+    format!(
+        "line {line}: \\{command}{{{}}} -> \\{command}{{{}}} ({decision})",
+        import.original,
+        import.rewritten,
+        command = import.command,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::Commands;
+
+    fn replace_imports(line: &str) -> Cow<'_, str> {
+        trace_imports(
+            line,
+            &Args::with_flags(&[]),
+            &Mapping::default(),
+            &Scope::default(),
+            |_| {},
+        )
+    }
+
+    #[test]
+    fn test_replace_imports_input() {
+        let line = r"\input{content/background}";
+        let expected = r"\input{content__background}";
+
+        assert_eq!(replace_imports(line), expected);
+    }
+
+    #[test]
+    fn test_replace_imports_include() {
+        let line = r"\include{content/background}";
+        let expected = r"\include{content__background}";
+
+        assert_eq!(replace_imports(line), expected);
+    }
+
+    #[test]
+    fn test_replace_imports_bibliography() {
+        let line = r"\bibliography{bibliography/references}";
+        let expected = r"\bibliography{bibliography__references}";
+
+        assert_eq!(replace_imports(line), expected);
+    }
+
+    #[test]
+    fn test_replace_imports_bibliography_custom() {
+        let line = r"\bibliographyS{bibliography/references}";
+        let expected = r"\bibliographyS{bibliography__references}";
+
+        assert_eq!(replace_imports(line), expected);
+    }
+
+    #[test]
+    fn test_replace_imports_includegraphics() {
+        let line = r"\includegraphics{figures/search_process.pdf}";
+        let expected = r"\includegraphics{figures__search_process.pdf}";
+
+        assert_eq!(replace_imports(line), expected);
+    }
+
+    #[test]
+    fn test_replace_imports_includegraphics_options() {
+        let line = r"\includegraphics[width=0.8\linewidth]{figures/search_process.pdf}";
+        let expected = r"\includegraphics[width=0.8\linewidth]{figures__search_process.pdf}";
+
+        assert_eq!(replace_imports(line), expected);
+    }
+
+    #[test]
+    fn test_replace_imports_loadglsentries() {
+        let line = r"\loadglsentries{glossary/acronyms}";
+        let expected = r"\loadglsentries{glossary__acronyms}";
+
+        assert_eq!(replace_imports(line), expected);
+    }
+
+    #[test]
+    fn test_replace_imports_loadglsentries_type() {
+        let line = r"\loadglsentries[acronym]{glossary/acronyms}";
+        let expected = r"\loadglsentries[acronym]{glossary__acronyms}";
+
+        assert_eq!(replace_imports(line), expected);
+    }
+
+    #[test]
+    fn test_replace_imports_includestandalone() {
+        let mut mapping = Mapping::default();
+        mapping.insert("figures/diagram.tex".into(), "figures__diagram.tex".into());
+        mapping.insert("figures/diagram.pdf".into(), "figures__diagram.pdf".into());
+        let line = r"\includestandalone[mode=image]{figures/diagram}";
+        let expected = r"\includestandalone[mode=image]{figures__diagram}";
+
+        let mut targets = Vec::new();
+        let rewritten = trace_imports(
+            line,
+            &Args::with_flags(&[]),
+            &mapping,
+            &Scope::default(),
+            |import| targets.extend(import.targets),
+        );
+
+        assert_eq!(rewritten, expected);
+        assert_eq!(targets, vec!["figures/diagram.tex", "figures/diagram.pdf"]);
+    }
+
+    #[test]
+    fn test_replace_imports_expands_macros() {
+        let mut scope = Scope::default();
+        scope.macros.define(r"\def\buildmode{release}");
+        let line = r"\input{build/\buildmode/summary} \input{\builddir/summary}";
+        let expected = r"\input{build__release__summary} \input{\builddir__summary}";
+
+        let mut undefined = Vec::new();
+        let rewritten = trace_imports(
+            line,
+            &Args::with_flags(&[]),
+            &Mapping::default(),
+            &scope,
+            |import| undefined.extend(import.undefined_macros),
+        );
+
+        assert_eq!(rewritten, expected);
+        assert_eq!(undefined, vec!["builddir"]);
+    }
+
+    #[test]
+    fn test_replace_imports_root_macro() {
+        let mut mapping = Mapping::default();
+        mapping.insert("chapters/intro.tex".into(), "chapters__intro.tex".into());
+        let mut scope = Scope::default();
+        scope
+            .macros
+            .define(r"\makeatletter\def\projroot{./}\def\@abs{/srv/}\makeatother");
+        let line = r"\input{\projroot chapters/intro} \input{\@abs intro}";
+        let expected = r"\input{chapters__intro} \input{/srv/intro}";
+
+        let mut imports = Vec::new();
+        let rewritten = trace_imports(line, &Args::with_flags(&[]), &mapping, &scope, |import| {
+            imports.push(import)
+        });
+
+        assert_eq!(rewritten, expected);
+        assert_eq!(imports[0].targets, vec!["chapters/intro.tex"]);
+        assert_eq!(imports[1].absolute_macros, vec!["@abs"]);
+    }
+
+    #[test]
+    fn test_replace_imports_macro_parameter() {
+        let line = r"\newcommand{\loadfig}[1]{\includegraphics[width=\linewidth]{figures/#1}}";
+        let expected = r"\newcommand{\loadfig}[1]{\includegraphics[width=\linewidth]{figures__#1}}";
+
+        assert_eq!(replace_imports(line), expected);
+        assert_eq!(replace_imports(r"\loadfig{plot}"), r"\loadfig{plot}");
+
+        let mut unresolved = Vec::new();
+        trace_imports(
+            line,
+            &Args::with_flags(&[]),
+            &Mapping::default(),
+            &Scope::default(),
+            |import| unresolved.extend(import.unresolved),
+        );
+        assert!(unresolved.is_empty());
+    }
+
+    #[test]
+    fn test_replace_imports_usepackage() {
+        let mut mapping = Mapping::default();
+        mapping.insert("amsmath.sty".into(), "amsmath.sty".into());
+        mapping.insert("styles/thesis.sty".into(), "styles__thesis.sty".into());
+        let line = r"\usepackage[utf8]{inputenc} \usepackage{amsmath, styles/thesis,siunitx}";
+        let expected = r"\usepackage[utf8]{inputenc} \usepackage{amsmath, styles__thesis,siunitx}";
+
+        let mut targets = Vec::new();
+        let rewritten = trace_imports(
+            line,
+            &Args::with_flags(&[]),
+            &mapping,
+            &Scope::default(),
+            |import| targets.extend(import.targets),
+        );
+
+        assert_eq!(rewritten, expected);
+        assert_eq!(targets, vec!["styles/thesis.sty"]);
+    }
+
+    #[test]
+    fn test_replace_imports_class_loading() {
+        let mut mapping = Mapping::default();
+        mapping.insert("styles/thesis.sty".into(), "styles__thesis.sty".into());
+        mapping.insert("classes/base.cls".into(), "classes__base.cls".into());
+        let line = r"\documentclass{classes/base} \RequirePackage{xcolor,styles/thesis} \LoadClass[a4paper]{article}";
+        let expected = r"\documentclass{classes__base} \RequirePackage{xcolor,styles__thesis} \LoadClass[a4paper]{article}";
+
+        let mut targets = Vec::new();
+        let mut unresolved = Vec::new();
+        let rewritten = trace_imports(
+            line,
+            &Args::with_flags(&[]),
+            &mapping,
+            &Scope::default(),
+            |import| {
+                targets.extend(import.targets);
+                unresolved.extend(import.unresolved);
+            },
+        );
+
+        assert_eq!(rewritten, expected);
+        assert_eq!(targets, vec!["classes/base.cls", "styles/thesis.sty"]);
+        assert!(unresolved.is_empty());
+    }
+
+    #[test]
+    fn test_replace_imports_protective_wrappers() {
+        let mut scope = Scope::default();
+        scope.macros.define(r"\def\figdir{figures}");
+        let line = r"\includegraphics{\protect\figdir/plot} \includegraphics{\detokenize{figures/my_plot}}";
+        let expected =
+            r"\includegraphics{figures__plot} \includegraphics{\detokenize{figures__my_plot}}";
+
+        let rewritten = trace_imports(
+            line,
+            &Args::with_flags(&[]),
+            &Mapping::default(),
+            &scope,
+            |_| {},
+        );
+        assert_eq!(rewritten, expected);
+        assert_eq!(
+            replace_imports(r"\input{content/\string~notes}"),
+            r"\input{content__~notes}"
+        );
+    }
+
+    #[test]
+    fn test_trace_imports_commented() {
+        let line = "\\input{chapters/a} % \\input{chapters/old}";
+        let mut imports = Vec::new();
+        let rewritten = trace_imports(
+            line,
+            &Args::with_flags(&[]),
+            &Mapping::default(),
+            &Scope::default(),
+            |import| imports.push((import.original, import.skipped)),
+        );
+
+        assert_eq!(rewritten, "\\input{chapters__a} % \\input{chapters/old}");
+        assert_eq!(
+            imports,
+            vec![
+                ("chapters/a".to_string(), None),
+                ("chapters/old".to_string(), Some(SkipReason::Commented))
+            ]
+        );
+        assert_eq!(
+            replace_imports("% \\input{chapters/old}"),
+            "% \\input{chapters/old}"
+        );
+        assert_eq!(
+            replace_imports("\\input{chapters/%\n  intro}"),
+            "\\input{chapters__intro}"
+        );
+    }
+
+    #[test]
+    fn test_trace_imports_protected() {
+        let line = r"\includegraphics{\protect\somepath/plot} \input{\string\jobname/notes}";
+        let mut protected = Vec::new();
+        let rewritten = trace_imports(
+            line,
+            &Args::with_flags(&[]),
+            &Mapping::default(),
+            &Scope::default(),
+            |import| protected.push(import.skipped),
+        );
+
+        assert_eq!(rewritten, line);
+        assert_eq!(protected, vec![Some(SkipReason::Protected); 2]);
+    }
+
+    #[test]
+    fn test_replace_imports_backslash_separator() {
+        let line = r"\input{content\background}";
+        let expected = r"\input{content__background}";
+
+        assert_eq!(replace_imports(line), expected);
+    }
+
+    #[test]
+    fn test_replace_imports_double_backslash_separator() {
+        let line = r"\includegraphics{appendix\\figures\\plot.pdf}";
+        let expected = r"\includegraphics{appendix__figures__plot.pdf}";
+
+        assert_eq!(replace_imports(line), expected);
+    }
+
+    #[test]
+    fn test_replace_imports_outside_root() {
+        let line = r"\input{../outside/x} \includegraphics{..\\figures\\plot.pdf}";
+        let expected = r"\input{../outside/x} \includegraphics{../figures/plot.pdf}";
+
+        assert_eq!(replace_imports(line), expected);
+    }
+
+    #[test]
+    fn test_replace_imports_keeps_escapes() {
+        let line = r"\input{\jobname-content/my\_file}";
+        let expected = r"\input{\jobname-content__my\_file}";
+
+        assert_eq!(replace_imports(line), expected);
+    }
+
+    #[test]
+    fn test_replace_imports_reference_extension_add() {
+        let mut mapping = Mapping::default();
+        mapping.insert("chapters/intro.tex".into(), "chapters__intro.tex".into());
+        let line = r"\include{chapters/intro} \includegraphics{chapters/intro}";
+        let expected = r"\include{chapters__intro.tex} \includegraphics{chapters__intro}";
+
+        let args = Args::with_flags(&["--reference-extension", "add"]);
+        assert_eq!(
+            trace_imports(line, &args, &mapping, &Scope::default(), |_| {}),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_replace_imports_input_braceless() {
+        let line = r"\input content/background \relax";
+        let expected = r"\input content__background \relax";
+
+        assert_eq!(replace_imports(line), expected);
+    }
+
+    #[test]
+    fn test_replace_imports_input_braced_with_space() {
+        let line = r"\input {content/background} and more";
+        let expected = r"\input {content__background} and more";
+
+        assert_eq!(replace_imports(line), expected);
+    }
+
+    #[test]
+    fn test_replace_imports_input_braceless_in_group() {
+        let line = r"{\input content/background}";
+        let expected = r"{\input content__background}";
+
+        assert_eq!(replace_imports(line), expected);
+    }
+
+    #[test]
+    fn test_replace_imports_primitive_input() {
+        let line = r"\@@input content/x \@@input{content/y}\relax";
+        let expected = r"\@@input content__x \@@input{content__y}\relax";
+
+        assert_eq!(replace_imports(line), expected);
+    }
+
+    #[test]
+    fn test_replace_imports_quoted() {
+        let line = r#"\includegraphics{"figures/my plot.pdf"} \input{"content/intro"}"#;
+        let expected = r#"\includegraphics{"figures__my plot.pdf"} \input{content__intro}"#;
+
+        assert_eq!(replace_imports(line), expected);
+    }
+
+    #[test]
+    fn test_replace_imports_custom_commands() {
+        let mut args = Args::with_flags(&[]);
+        args.commands = Commands::with_custom([
+            ("deptbib".to_string(), CommandKind::Bibliography),
+            ("includesvg".to_string(), CommandKind::Graphics),
+        ]);
+        let mut mapping = Mapping::default();
+        mapping.insert(
+            "bibliography/refs.bib".into(),
+            "bibliography__refs.bib".into(),
+        );
+        mapping.insert("figures/plot.svg".into(), "figures__plot.svg".into());
+
+        let line = r"\deptbib{bibliography/refs,other/refs} \includesvg[width=1cm]{figures/plot}";
+        let expected =
+            r"\deptbib{bibliography__refs,other__refs} \includesvg[width=1cm]{figures__plot}";
+        let mut targets = Vec::new();
+        let rewritten = trace_imports(line, &args, &mapping, &Scope::default(), |import| {
+            targets.extend(import.targets)
+        });
+
+        assert_eq!(rewritten, expected);
+        assert_eq!(targets, vec!["bibliography/refs.bib", "figures/plot.svg"]);
+    }
+
+    #[test]
+    fn test_replace_imports_preserve_command() {
+        let mut args = Args::with_flags(&["--preserve-command", r"\url"]);
+        args.commands = Commands::with_custom([("url".to_string(), CommandKind::Graphics)]);
+
+        let line = r"\url{http://a/b} \includegraphics{figures/plot}";
+        let expected = r"\url{http://a/b} \includegraphics{figures__plot}";
+        let mut imports = Vec::new();
+        let rewritten = trace_imports(
+            line,
+            &args,
+            &Mapping::default(),
+            &Scope::default(),
+            |import| imports.push(import),
+        );
+
+        assert_eq!(rewritten, expected);
+        assert_eq!(
+            imports[0],
+            Import::skipped("url", "http://a/b", SkipReason::Preserved)
+        );
+        assert_eq!(
+            explanation(1, &imports[0]),
+            r"line 1: \url{http://a/b} -> \url{http://a/b} (skipped: preserved)"
+        );
+        assert_eq!(imports[1].skipped, None);
+        assert!(Args::from_flags(["--preserve-command", "url{"]).is_err());
+    }
+
+    #[test]
+    fn test_replace_imports_path_arg() {
+        let mut args = Args::with_flags(&[]);
+        args.commands = Commands::with_custom_path_args([
+            ("myinclude".to_string(), CommandKind::Graphics, 2),
+            ("import".to_string(), CommandKind::Input, 2),
+        ]);
+
+        let line = r"\myinclude{A plot/figure}{figures/plot} \myinclude[w]{a/b} {figures/c}{x/y}";
+        let expected =
+            r"\myinclude{A plot/figure}{figures__plot} \myinclude[w]{a/b} {figures__c}{x/y}";
+        assert_eq!(
+            trace_imports(line, &args, &Mapping::default(), &Scope::default(), |_| {}),
+            expected
+        );
+        assert_eq!(
+            trace_imports(
+                r"\myinclude{only/one}",
+                &args,
+                &Mapping::default(),
+                &Scope::default(),
+                |_| {}
+            ),
+            r"\myinclude{only/one}"
+        );
+    }
+
+    #[test]
+    fn test_trace_imports_mismatched_extension() {
+        let mut mapping = Mapping::default();
+        mapping.insert("figures/plot.pdf".into(), "figures__plot.pdf".into());
+        let line = r"\includegraphics{figures/plot.eps}";
+
+        for (flags, expected) in [
+            (&[][..], r"\includegraphics{figures__plot.eps}"),
+            (
+                &["--fix-extensions"][..],
+                r"\includegraphics{figures__plot.pdf}",
+            ),
+        ] {
+            let mut imports = Vec::new();
+            let rewritten = trace_imports(
+                line,
+                &Args::with_flags(flags),
+                &mapping,
+                &Scope::default(),
+                |import| imports.push(import),
+            );
+
+            assert_eq!(rewritten, expected);
+            assert_eq!(
+                imports[0].mismatched_extensions,
+                vec![(
+                    "figures/plot.eps".to_string(),
+                    "figures/plot.pdf".to_string()
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn test_trace_imports_outside_root() {
+        let line = r"\input{../../secret/notes} \bibliography{refs,/home/me/refs}";
+        let mut outside_root = Vec::new();
+        trace_imports(
+            line,
+            &Args::with_flags(&[]),
+            &Mapping::default(),
+            &Scope::default(),
+            |import| outside_root.extend(import.outside_root),
+        );
+
+        assert_eq!(outside_root, vec!["../../secret/notes", "/home/me/refs"]);
+    }
+
+    #[test]
+    fn test_replace_imports_bibliography_style() {
+        let mut mapping = Mapping::default();
+        mapping.insert("styles/thesis.bst".into(), "styles__thesis.bst".into());
+        let line = r"\bibliographystyle{plain} \bibliographystyle{styles/thesis} \addbibresource{refs/main.bib}";
+        let expected = r"\bibliographystyle{plain} \bibliographystyle{styles__thesis} \addbibresource{refs__main.bib}";
+
+        let mut targets = Vec::new();
+        let mut unresolved = Vec::new();
+        let rewritten = trace_imports(
+            line,
+            &Args::with_flags(&[]),
+            &mapping,
+            &Scope::default(),
+            |import| {
+                targets.extend(import.targets);
+                unresolved.extend(import.unresolved);
+            },
+        );
+
+        assert_eq!(rewritten, expected);
+        assert_eq!(targets, vec!["styles/thesis.bst"]);
+        assert_eq!(unresolved, vec!["refs/main.bib"]);
+    }
+
+    #[test]
+    fn test_trace_imports_absolute() {
+        let line = r"\includegraphics{C:/Users/me/fig.pdf} \includegraphics{D:\Data\plot.png} \input{/home/me/notes}";
+        let mut outside_root = Vec::new();
+        let rewritten = trace_imports(
+            line,
+            &Args::with_flags(&[]),
+            &Mapping::default(),
+            &Scope::default(),
+            |import| outside_root.extend(import.outside_root),
+        );
+
+        // Absolute paths are left unchanged instead of mangled
+        assert_eq!(
+            rewritten,
+            r"\includegraphics{C:/Users/me/fig.pdf} \includegraphics{D:/Data/plot.png} \input{/home/me/notes}"
+        );
+        assert_eq!(
+            outside_root,
+            vec!["C:/Users/me/fig.pdf", "D:/Data/plot.png", "/home/me/notes"]
+        );
+
+        let mut mapping = Mapping::default();
+        mapping.insert_external(
+            "D:/Data/plot.png",
+            crate::mapping::external_name("D:/Data/plot.png"),
+        );
+        assert_eq!(
+            trace_imports(
+                r"\includegraphics{D:\Data\plot.png}",
+                &Args::with_flags(&[]),
+                &mapping,
+                &Scope::default(),
+                |_| {}
+            ),
+            r"\includegraphics{external__D__Data__plot.png}"
+        );
+    }
+
+    #[test]
+    fn test_find_unterminated_imports() {
+        let args = Args::with_flags(&[]);
+
+        assert_eq!(
+            find_unterminated_imports(r"\input{content/background", &args),
+            vec!["input"]
+        );
+        assert_eq!(
+            find_unterminated_imports(r"\includegraphics[width=1cm]{fig/plot \input{a}", &args),
+            vec!["includegraphics"]
+        );
+        assert!(find_unterminated_imports(r"\input{content/background}", &args).is_empty());
+        assert!(find_unterminated_imports(r"\input{\dir{x}/background}", &args).is_empty());
+    }
+
+    #[test]
+    fn test_join_continued_commands() {
+        let args = Args::with_flags(&[]);
+        let content = "\\includegraphics % chosen figure\r\n[width=1cm]{fig/plot}\n\
+                       % \\input\n{x}\n\\includegraphics[width=1cm]%\n  {fig/a}";
+
+        let lines = join_continued_commands(content, &args);
+        assert_eq!(
+            lines,
+            vec![
+                (
+                    0,
+                    "\\includegraphics % chosen figure\r\n[width=1cm]{fig/plot}",
+                    "\n"
+                ),
+                (2, "% \\input", "\n"),
+                (3, "{x}", "\n"),
+                (4, "\\includegraphics[width=1cm]%\n  {fig/a}", ""),
+            ]
+        );
+        assert_eq!(
+            replace_imports(lines[0].1),
+            "\\includegraphics % chosen figure\r\n[width=1cm]{fig__plot}"
+        );
+        assert_eq!(
+            replace_imports(lines[3].1),
+            "\\includegraphics[width=1cm]%\n  {fig__a}"
+        );
+    }
+
+    #[test]
+    fn test_path_split_by_comment() {
+        let args = Args::with_flags(&[]);
+        let content = "\\input{chapters/%\n  intro}\n\\input{chapters/outro\nText % comment\n";
+
+        let lines = join_continued_commands(content, &args);
+        assert_eq!(
+            lines,
+            vec![
+                (0, "\\input{chapters/%\n  intro}", "\n"),
+                (2, "\\input{chapters/outro", "\n"),
+                (3, "Text % comment", "\n"),
+            ]
+        );
+        assert_eq!(replace_imports(lines[0].1), "\\input{chapters__intro}");
+        assert_eq!(
+            replace_imports("\\includegraphics[width=1cm]{figures/%\r\nplots/%\nresult}"),
+            "\\includegraphics[width=1cm]{figures__plots__result}"
+        );
+    }
+
+    #[test]
+    fn test_flatten_links() {
+        let mut mapping = Mapping::default();
+        mapping.insert("docs/appendix.pdf".into(), "docs__appendix.pdf".into());
+
+        let mut targets = Vec::new();
+        let line = r"\href{docs/appendix.pdf}{A} \href{run:./docs/appendix.pdf#page=2}{B} % \url{docs/appendix.pdf}";
+        assert_eq!(
+            flatten_links(line, &mapping, |target| targets.push(target.to_string())),
+            r"\href{docs__appendix.pdf}{A} \href{run:docs__appendix.pdf#page=2}{B} % \url{docs/appendix.pdf}"
+        );
+        assert_eq!(targets, vec!["docs/appendix.pdf", "docs/appendix.pdf"]);
+
+        for line in [
+            r"\href{https://example.com/docs/appendix.pdf}{C}",
+            r"\url{http://example.com}",
+            r"\href{mailto:me@example.com}{D}",
+            r"\href{docs/missing.pdf}{E}",
+        ] {
+            assert!(matches!(
+                flatten_links(line, &mapping, |_| panic!("{line} has no local target")),
+                Cow::Borrowed(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_find_unsupported_commands() {
+        let args = Args::with_flags(&[]);
+        let mut mapping = Mapping::default();
+        mapping.insert("figures/x.pdf".into(), "figures__x.pdf".into());
+
+        let line = r"\myfig{figures/x} \includegraphics{figures/x} \href{figures/x}{x} \other{a/b}";
+        assert_eq!(
+            find_unsupported_commands(line, &args, &mapping),
+            vec![UnsupportedCommand {
+                command: "myfig",
+                argument: "figures/x",
+                target: "figures/x.pdf".to_string(),
+            }]
+        );
+        assert!(find_unsupported_commands(r"% \myfig{figures/x}", &args, &mapping).is_empty());
+    }
+
+    #[test]
+    fn test_trace_imports() {
+        let line = r"\input{content/background} \includegraphics[width=3cm]{plot.pdf}";
+        let mut imports = Vec::new();
+        trace_imports(
+            line,
+            &Args::with_flags(&[]),
+            &Mapping::default(),
+            &Scope::default(),
+            |import| imports.push(import),
+        );
+
+        assert_eq!(
+            imports,
+            vec![
+                Import {
+                    command: "input".to_string(),
+                    original: "content/background".to_string(),
+                    rewritten: "content__background".to_string(),
+                    targets: vec![],
+                    mismatched_extensions: vec![],
+                    outside_root: vec![],
+                    unresolved: vec!["content/background".to_string()],
+                    undefined_macros: vec![],
+                    absolute_macros: vec![],
+                    skipped: None,
+                },
+                Import {
+                    command: "includegraphics".to_string(),
+                    original: "plot.pdf".to_string(),
+                    rewritten: "plot.pdf".to_string(),
+                    targets: vec![],
+                    mismatched_extensions: vec![],
+                    outside_root: vec![],
+                    unresolved: vec!["plot.pdf".to_string()],
+                    undefined_macros: vec![],
+                    absolute_macros: vec![],
+                    skipped: None,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,417 @@
+use crate::deps::strip_comment;
+use regex::Regex;
+use std::{path::Path, sync::LazyLock};
+
+/// A control word, like `\iffalse`.
+static CONTROL_WORD_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\\([A-Za-z@]+)").unwrap());
+
+/// The environments whose content is printed as written, where whitespace can be significant.
+const VERBATIM_ENVIRONMENTS: [&str; 5] =
+    ["verbatim", "verbatim*", "Verbatim", "lstlisting", "minted"];
+
+/// What a line of a file contains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    /// LaTeX source.
+    Latex,
+    /// The content of a verbatim environment.
+    Verbatim,
+    /// Code that is not LaTeX, like a code chunk of a noweb file including its delimiters,
+    /// the package code of a `.dtx` file, or a fenced code block of a Markdown file.
+    Code,
+    /// LaTeX source disabled by `\iffalse ... \fi`, which TeX skips up to the matching `\else`.
+    Disabled,
+}
+
+/// The kinds of files mixing LaTeX with other content.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Syntax {
+    #[default]
+    Latex,
+    /// knitr and Sweave `.Rnw` files.
+    Noweb,
+    /// Documented LaTeX sources in `.dtx` files.
+    Docstrip,
+    /// Markdown files with raw LaTeX, like for Pandoc.
+    Markdown,
+}
+
+/// Tracks which lines of a file are LaTeX and which must be left untouched.
+///
+/// In noweb files like knitr's and Sweave's `.Rnw`, code chunks start with a `<<...>>=` line
+/// and end with a line starting with `@`.
+///
+/// In `.dtx` files, only the documentation in `%` comments and the driver are LaTeX.
+/// The package code, `macrocode` environments and `\iffalse` guarded comments are not.
+///
+/// In Markdown files, code blocks fenced by ```` ``` ```` or `~~~` are not LaTeX.
+#[derive(Debug, Default)]
+pub struct LineScanner {
+    syntax: Syntax,
+    /// In a noweb code chunk or a `macrocode` environment.
+    in_chunk: bool,
+    /// In a `% \iffalse` guard of a `.dtx` file.
+    in_guard: bool,
+    /// In the `<*driver>` part of a `.dtx` file.
+    in_driver: bool,
+    /// The character and length of the fence of the Markdown code block the scanner is in.
+    fence: Option<(char, usize)>,
+    /// The verbatim environment the scanner is in.
+    verbatim: Option<&'static str>,
+    /// The number of open conditionals in an `\iffalse` block, or 0 outside of one.
+    disabled_depth: usize,
+}
+
+impl LineScanner {
+    /// A scanner for the file at the given path, recognizing the syntax of
+    /// `.Rnw`, `.dtx` and `.md` files.
+    pub fn new(path: &Path) -> Self {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let syntax = match extension {
+            Some(ext) if ext.eq_ignore_ascii_case("rnw") => Syntax::Noweb,
+            Some(ext) if ext.eq_ignore_ascii_case("dtx") => Syntax::Docstrip,
+            Some(ext) if ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown") => {
+                Syntax::Markdown
+            }
+            _ => Syntax::Latex,
+        };
+
+        Self {
+            syntax,
+            ..Self::default()
+        }
+    }
+
+    /// Determine what the next line of the file contains.
+    ///
+    /// Must be called for every line in order.
+    pub fn scan(&mut self, line: &str) -> LineKind {
+        match self.syntax {
+            Syntax::Latex => {}
+            Syntax::Noweb => {
+                let trimmed = line.trim_end();
+                if self.verbatim.is_none() && trimmed.starts_with("<<") && trimmed.ends_with(">>=")
+                {
+                    self.in_chunk = true;
+                    return LineKind::Code;
+                }
+
+                if self.in_chunk {
+                    if line.starts_with('@') {
+                        self.in_chunk = false;
+                    }
+                    return LineKind::Code;
+                }
+            }
+            Syntax::Docstrip => {
+                if self.verbatim.is_none() && self.is_docstrip_code(line) {
+                    return LineKind::Code;
+                }
+            }
+            Syntax::Markdown => {
+                if self.verbatim.is_none() && self.is_fenced_code(line) {
+                    return LineKind::Code;
+                }
+            }
+        }
+
+        if let Some(environment) = self.verbatim {
+            // The line ending the environment is verbatim up to the `\end`
+            if line.contains(&format!(r"\end{{{environment}}}")) {
+                self.verbatim = None;
+            }
+            return LineKind::Verbatim;
+        }
+
+        if self.is_disabled(line) {
+            return LineKind::Disabled;
+        }
+
+        self.verbatim = VERBATIM_ENVIRONMENTS.into_iter().find(|environment| {
+            line.find(&format!(r"\begin{{{environment}}}"))
+                .is_some_and(|start| !line[start..].contains(&format!(r"\end{{{environment}}}")))
+        });
+        LineKind::Latex
+    }
+
+    /// Determine if the line starts in an `\iffalse` block or starts one,
+    /// tracking the nested conditionals up to the matching `\else` or `\fi`.
+    /// The `\else` branch is processed by TeX, so it is not disabled.
+    fn is_disabled(&mut self, line: &str) -> bool {
+        let code = strip_comment(line);
+        let starts_disabled = self.disabled_depth > 0;
+        let mut opens_block = false;
+        // The conditional defined by `\newif` is not a conditional yet
+        let mut after_newif = false;
+
+        for capture in CONTROL_WORD_REGEX.captures_iter(code) {
+            let word = &capture[1];
+            if self.disabled_depth == 0 {
+                if word == "iffalse" {
+                    opens_block = code[..capture.get(0).unwrap().start()].trim().is_empty();
+                    self.disabled_depth = 1;
+                }
+            } else if word == "fi" || (word == "else" && self.disabled_depth == 1) {
+                self.disabled_depth -= 1;
+            } else if word.starts_with("if") && !after_newif {
+                self.disabled_depth += 1;
+            }
+            after_newif = word == "newif";
+        }
+
+        starts_disabled || opens_block
+    }
+
+    /// Determine if the line of a Markdown file is part of a fenced code block,
+    /// including its fences.
+    fn is_fenced_code(&mut self, line: &str) -> bool {
+        match (self.fence, code_fence(line)) {
+            (Some((open_char, open_length)), Some((char, length))) => {
+                // A closing fence has no info string, like the language of the opening one
+                let rest = line.trim_start_matches(' ').trim_start_matches(char);
+                if char == open_char && length >= open_length && rest.trim().is_empty() {
+                    self.fence = None;
+                }
+                true
+            }
+            (Some(_), None) => true,
+            (None, Some(fence)) => {
+                self.fence = Some(fence);
+                true
+            }
+            (None, None) => false,
+        }
+    }
+
+    /// Determine if the line of a `.dtx` file is not LaTeX, advancing past its delimiters.
+    fn is_docstrip_code(&mut self, line: &str) -> bool {
+        let comment = line.strip_prefix('%');
+        let command = comment.map(str::trim_start);
+
+        if self.in_chunk {
+            if command.is_some_and(|command| command.starts_with(r"\end{macrocode}")) {
+                self.in_chunk = false;
+            }
+            return true;
+        }
+
+        let Some(comment) = comment else {
+            // Package code, except for the driver
+            return !self.in_driver;
+        };
+        let command = command.unwrap();
+
+        if command.starts_with(r"\begin{macrocode}") {
+            self.in_chunk = true;
+        } else if comment.starts_with("<*driver>") {
+            self.in_driver = true;
+        } else if comment.starts_with("</driver>") {
+            self.in_driver = false;
+        } else if command.starts_with(r"\iffalse") {
+            self.in_guard = true;
+        } else if self.in_guard {
+            if command.starts_with(r"\fi") {
+                self.in_guard = false;
+            }
+        } else {
+            // Documentation, unless it is a docstrip guard like `%<*package>`
+            return comment.starts_with('<');
+        }
+
+        true
+    }
+}
+
+/// The character and length of the code fence the line starts with, like ```` ``` ````,
+/// which can be indented by up to three spaces.
+fn code_fence(line: &str) -> Option<(char, usize)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+
+    let char = trimmed
+        .chars()
+        .next()
+        .filter(|char| matches!(char, '`' | '~'))?;
+    let length = trimmed.len() - trimmed.trim_start_matches(char).len();
+    (length >= 3).then_some((char, length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(path: &str, content: &str) -> Vec<LineKind> {
+        let mut scanner = LineScanner::new(Path::new(path));
+        content.lines().map(|line| scanner.scan(line)).collect()
+    }
+
+    #[test]
+    fn test_noweb_chunks() {
+        let content =
+            "\\input{intro}\n<<plot, echo=FALSE>>=\nx <- \"\\\\input{no}\"\n@\n\\input{outro}";
+
+        assert_eq!(
+            scan("report.Rnw", content),
+            vec![
+                LineKind::Latex,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Latex
+            ]
+        );
+    }
+
+    #[test]
+    fn test_markdown_code_blocks() {
+        let content = [
+            r"# Results",
+            r"\includegraphics{figures/plot}",
+            r"````latex",
+            r"\input{example}",
+            r"```",
+            r"````",
+            r"~~~",
+            r"\input{example}",
+            r"~~~",
+            r"Text with \input{chapters/intro}",
+        ];
+
+        assert_eq!(
+            scan("paper.md", &content.join("\n")),
+            vec![
+                LineKind::Latex,
+                LineKind::Latex,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Latex,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tex_has_no_chunks() {
+        assert_eq!(
+            scan("main.tex", "<<plot>>=\n@"),
+            vec![LineKind::Latex, LineKind::Latex]
+        );
+    }
+
+    #[test]
+    fn test_docstrip() {
+        let content = [
+            r"% \iffalse meta-comment",
+            r"% \input{license}",
+            r"% \fi",
+            r"% \iffalse",
+            r"%<*driver>",
+            r"\documentclass{ltxdoc}",
+            r"%</driver>",
+            r"% \fi",
+            r"% \includegraphics{figures/example}",
+            r"%    \begin{macrocode}",
+            r"\input{code}",
+            r"%    \end{macrocode}",
+            r"%<*package>",
+            r"\RequirePackage{xcolor}",
+        ];
+
+        assert_eq!(
+            scan("mypkg.dtx", &content.join("\n")),
+            vec![
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Latex,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Latex,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Code,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_iffalse() {
+        let content = [
+            r"\input{intro}",
+            r"\iffalse % disabled",
+            r"\input{draft}",
+            r"\ifdraft \input{notes} \else \fi \newif\ifold",
+            r"\fi \input{still-disabled}",
+            r"\iffalse \input{inline} \fi",
+            r"\input{outro} % \iffalse",
+            r"\includegraphics{plot}",
+        ];
+
+        assert_eq!(
+            scan("main.tex", &content.join("\n")),
+            vec![
+                LineKind::Latex,
+                LineKind::Disabled,
+                LineKind::Disabled,
+                LineKind::Disabled,
+                LineKind::Disabled,
+                LineKind::Disabled,
+                LineKind::Latex,
+                LineKind::Latex,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_iffalse_else() {
+        let content = [
+            r"\iffalse",
+            r"\input{draft}",
+            r"\ifdraft \input{notes} \else \input{final} \fi",
+            r"\else",
+            r"\input{live}",
+            r"\fi",
+            r"\input{outro}",
+        ];
+
+        assert_eq!(
+            scan("main.tex", &content.join("\n")),
+            vec![
+                LineKind::Disabled,
+                LineKind::Disabled,
+                LineKind::Disabled,
+                LineKind::Disabled,
+                LineKind::Latex,
+                LineKind::Latex,
+                LineKind::Latex,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_verbatim() {
+        let content = "\\begin{verbatim}\n  code  \n\\end{verbatim}\n\\verb|x| \\begin{lstlisting}x\\end{lstlisting}\ntext";
+
+        assert_eq!(
+            scan("main.tex", content),
+            vec![
+                LineKind::Latex,
+                LineKind::Verbatim,
+                LineKind::Verbatim,
+                LineKind::Latex,
+                LineKind::Latex
+            ]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{deps::strip_comment, macros::Macros, mapping::Mapping};
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// The `\graphicspath` command with its list of braced directories, like `{{figures/}{plots/}}`.
+static GRAPHICSPATH_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\\graphicspath[ \t]*\{((?:[ \t]*\{[^{}]*\})*)[ \t]*\}").unwrap());
+
+/// A directory of the `\graphicspath` list.
+static DIRECTORY_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{([^{}]*)\}").unwrap());
+
+/// The definitions in effect at a line of a file, which affect how include paths resolve.
+#[derive(Debug, Default)]
+pub struct Scope {
+    pub macros: Macros,
+    /// The directories graphics are also searched in, set with `\graphicspath`.
+    /// They are relative to the project root and end with a `/`.
+    pub graphics_paths: Vec<String>,
+}
+
+impl Scope {
+    /// A scope starting with the macros of the local packages and the given graphics paths,
+    /// e.g. those set by the including file.
+    pub fn with_graphics_paths(graphics_paths: Vec<String>, mapping: &Mapping) -> Self {
+        Self {
+            macros: mapping.package_macros().clone(),
+            graphics_paths,
+        }
+    }
+
+    /// The scope at the start of the file at the given source path.
+    pub fn for_file(source: &str, mapping: &Mapping) -> Self {
+        Self::with_graphics_paths(mapping.graphics_paths_at_start(source).to_vec(), mapping)
+    }
+
+    /// Continue after including the file at the given source path,
+    /// whose `\graphicspath` stays in effect.
+    pub fn include(&mut self, target: &str, mapping: &Mapping) {
+        if let Some(graphics_paths) = mapping.graphics_paths_at_end(target) {
+            self.graphics_paths = graphics_paths.to_vec();
+        }
+    }
+
+    /// Record the definitions of the line of the file at the given source path.
+    pub fn define(&mut self, line: &str, source: &str, mapping: &Mapping) {
+        self.macros.define(line);
+
+        if let Some(directories) = parse_graphicspath(strip_comment(line)) {
+            self.graphics_paths = directories
+                .into_iter()
+                .map(|directory| resolve_directory(directory, source, mapping))
+                .collect();
+        }
+    }
+}
+
+/// The directories of the last `\graphicspath` command of the line, if any.
+fn parse_graphicspath(line: &str) -> Option<Vec<&str>> {
+    let list = GRAPHICSPATH_REGEX.captures_iter(line).last()?.get(1)?;
+
+    Some(
+        DIRECTORY_REGEX
+            .captures_iter(list.as_str())
+            .map(|capture| capture.get(1).unwrap().as_str().trim())
+            .filter(|directory| !directory.is_empty())
+            .collect(),
+    )
+}
+
+/// Make a `\graphicspath` directory relative to the project root.
+///
+/// LaTeX resolves the directories relative to the main document. Projects which compile
+/// their chapters separately write them relative to the chapter instead, so if the directory
+/// doesn't exist in the project, it is tried relative to the declaring file.
+fn resolve_directory(directory: &str, source: &str, mapping: &Mapping) -> String {
+    let directory = directory.trim_end_matches('/');
+
+    if !mapping.has_directory(directory) {
+        if let Some((parent, _)) = source.rsplit_once('/') {
+            let relative = format!("{parent}/{directory}");
+            if mapping.has_directory(&relative) {
+                return format!("{relative}/");
+            }
+        }
+    }
+
+    format!("{directory}/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_graphicspath() {
+        assert_eq!(
+            parse_graphicspath(r"\graphicspath{{figures/}{ ../plots/ }}"),
+            Some(vec!["figures/", "../plots/"])
+        );
+        assert_eq!(parse_graphicspath(r"\graphicspath{}"), Some(vec![]));
+        assert_eq!(parse_graphicspath(r"\includegraphics{figures/plot}"), None);
+    }
+
+    #[test]
+    fn test_define_graphicspath() {
+        let mut mapping = Mapping::default();
+        mapping.insert("figures/a.pdf".into(), "figures__a.pdf".into());
+        mapping.insert(
+            "chapters/intro/img/b.pdf".into(),
+            "chapters__intro__img__b.pdf".into(),
+        );
+
+        let mut scope = Scope::default();
+        scope.define(
+            r"\graphicspath{{figures/}{img/}{missing}} % {{other/}}",
+            "chapters/intro/intro.tex",
+            &mapping,
+        );
+
+        assert_eq!(
+            scope.graphics_paths,
+            vec!["figures/", "chapters/intro/img/", "missing/"]
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SizeError {
+    #[error(
+        "Invalid size {0:?}, expected a number of bytes with an optional unit like 10MB or 512KiB"
+    )]
+    Invalid(String),
+    #[error("The size must be at least one byte")]
+    Zero,
+}
+
+/// The units of sizes, as a factor of bytes.
+const UNITS: [(&str, u64); 9] = [
+    ("B", 1),
+    ("KB", 1_000),
+    ("MB", 1_000_000),
+    ("GB", 1_000_000_000),
+    ("KIB", 1 << 10),
+    ("MIB", 1 << 20),
+    ("GIB", 1 << 30),
+    ("K", 1 << 10),
+    ("M", 1 << 20),
+];
+
+/// Parse a size in bytes with an optional unit, ignoring case, e.g. `1500`, `10MB` or `2 GiB`.
+///
+/// `KB`, `MB` and `GB` are powers of 1000, `KiB`, `MiB` and `GiB` as well as `K` and `M`
+/// are powers of 1024.
+pub fn parse_size(size: &str) -> Result<u64, SizeError> {
+    let invalid = || SizeError::Invalid(size.to_string());
+    let trimmed = size.trim();
+    let split = trimmed
+        .find(|char: char| !char.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let unit = unit.trim().to_ascii_uppercase();
+    let factor = if unit.is_empty() {
+        1
+    } else {
+        UNITS
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .map(|(_, factor)| *factor)
+            .ok_or_else(invalid)?
+    };
+
+    number.checked_mul(factor).ok_or_else(invalid)
+}
+
+/// Parse the size of a buffer like [`parse_size`], which must not be empty.
+pub fn parse_buffer_size(size: &str) -> Result<usize, SizeError> {
+    match parse_size(size)? {
+        0 => Err(SizeError::Zero),
+        size => usize::try_from(size).map_err(|_| SizeError::Invalid(size.to_string())),
+    }
+}
+
+/// Format a size in bytes with a decimal unit, like `1.5 MB`.
+pub fn format_size(bytes: u64) -> String {
+    let Some((unit, factor)) = UNITS[1..4]
+        .iter()
+        .rev()
+        .find(|(_, factor)| bytes >= *factor)
+    else {
+        return format!("{bytes} B");
+    };
+
+    format!("{:.1} {unit}", bytes as f64 / *factor as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1500"), Ok(1500));
+        assert_eq!(parse_size("10MB"), Ok(10_000_000));
+        assert_eq!(parse_size("2 GiB"), Ok(2 << 30));
+        assert_eq!(parse_size("512k"), Ok(512 << 10));
+        assert_eq!(parse_size("MB"), Err(SizeError::Invalid("MB".to_string())));
+        assert_eq!(
+            parse_size("1.5MB"),
+            Err(SizeError::Invalid("1.5MB".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_buffer_size() {
+        assert_eq!(parse_buffer_size("64KiB"), Ok(64 << 10));
+        assert_eq!(parse_buffer_size("0"), Err(SizeError::Zero));
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(999), "999 B");
+        assert_eq!(format_size(1_500), "1.5 KB");
+        assert_eq!(format_size(2_340_000_000), "2.3 GB");
+    }
+}
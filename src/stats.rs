@@ -0,0 +1,113 @@
+use crate::size::format_size;
+use serde::Serialize;
+use std::{cmp::Reverse, collections::BTreeMap, fmt::Write};
+
+/// The files of a top-level directory of the project, before flattening.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct DirectoryStats {
+    /// The first component of the source paths, or `.` for the files at the project root.
+    pub directory: String,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Group the `(source, size)` pairs of the files by their top-level directory.
+///
+/// The directories are sorted by size, largest first, to show where the bulk comes from.
+pub fn directory_stats(files: impl IntoIterator<Item = (String, u64)>) -> Vec<DirectoryStats> {
+    let mut directories: BTreeMap<String, (usize, u64)> = BTreeMap::new();
+
+    for (source, size) in files {
+        let directory = match source.split_once('/') {
+            Some((directory, _)) => directory.to_string(),
+            None => ".".to_string(),
+        };
+        let (files, bytes) = directories.entry(directory).or_default();
+        *files += 1;
+        *bytes += size;
+    }
+
+    let mut stats: Vec<_> = directories
+        .into_iter()
+        .map(|(directory, (files, bytes))| DirectoryStats {
+            directory,
+            files,
+            bytes,
+        })
+        .collect();
+    stats.sort_by_key(|stats| Reverse(stats.bytes));
+    stats
+}
+
+/// Render the statistics as an aligned table with a total row.
+pub fn render_stats(stats: &[DirectoryStats]) -> String {
+    let total = DirectoryStats {
+        directory: "total".to_string(),
+        files: stats.iter().map(|stats| stats.files).sum(),
+        bytes: stats.iter().map(|stats| stats.bytes).sum(),
+    };
+    let width = stats
+        .iter()
+        .chain([&total])
+        .map(|stats| stats.directory.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let mut table = String::new();
+    for stats in stats.iter().chain([&total]) {
+        writeln!(
+            table,
+            "{:<width$}  {:>6} file(s)  {:>10}",
+            stats.directory,
+            stats.files,
+            format_size(stats.bytes)
+        )
+        .unwrap();
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directory_stats() {
+        let files = [
+            ("main.tex", 2_000),
+            ("figures/a.pdf", 1_500_000),
+            ("chapters/intro.tex", 3_000),
+            ("figures/plots/b.png", 500_000),
+        ]
+        .map(|(source, size)| (source.to_string(), size));
+        let stats = directory_stats(files);
+
+        assert_eq!(
+            stats,
+            vec![
+                DirectoryStats {
+                    directory: "figures".to_string(),
+                    files: 2,
+                    bytes: 2_000_000
+                },
+                DirectoryStats {
+                    directory: "chapters".to_string(),
+                    files: 1,
+                    bytes: 3_000
+                },
+                DirectoryStats {
+                    directory: ".".to_string(),
+                    files: 1,
+                    bytes: 2_000
+                },
+            ]
+        );
+        assert_eq!(
+            render_stats(&stats),
+            "figures        2 file(s)      2.0 MB\n\
+             chapters       1 file(s)      3.0 KB\n\
+             .              1 file(s)      2.0 KB\n\
+             total          4 file(s)      2.0 MB\n"
+        );
+    }
+}
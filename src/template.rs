@@ -0,0 +1,237 @@
+use std::path::Path;
+use thiserror::Error;
+
+/// Characters which can't appear in file names referenced from LaTeX.
+const UNSAFE_CHARACTERS: [char; 11] = ['/', '\\', '%', '#', '{', '}', '~', '$', '&', '^', ' '];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error("Unknown variable {{{0}}}, expected one of dir, parent, stem, ext or hash")]
+    UnknownVariable(String),
+    #[error("Unknown modifier :{0}, expected lower or upper")]
+    UnknownModifier(String),
+    #[error("Unclosed variable")]
+    Unclosed,
+    #[error("The name {0:?} contains characters which are not safe to use in LaTeX")]
+    UnsafeName(String),
+    #[error("The template produced an empty name")]
+    EmptyName,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    /// The directory components, each followed by `__`.
+    Dir,
+    /// The name of the directory containing the file.
+    Parent,
+    /// The file name without extension.
+    Stem,
+    /// The extension, without the dot.
+    Ext,
+    /// A short hash of the file content.
+    Hash,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Modifier {
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Variable(Variable, Option<Modifier>),
+}
+
+/// A template for the flattened file names, e.g. `{dir}{stem}-{hash}.{ext}`.
+///
+/// Variables can be modified with `:lower` or `:upper`, e.g. `{stem:lower}`.
+/// For files without extension, `.{ext}` expands to nothing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenameTemplate {
+    segments: Vec<Segment>,
+}
+
+impl RenameTemplate {
+    pub fn parse(template: &str) -> Result<Self, TemplateError> {
+        let mut segments = Vec::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+
+            let end = rest[start..].find('}').ok_or(TemplateError::Unclosed)? + start;
+            let (name, modifier) = match rest[start + 1..end].split_once(':') {
+                Some((name, modifier)) => (name, Some(modifier)),
+                None => (&rest[start + 1..end], None),
+            };
+
+            let variable = match name {
+                "dir" => Variable::Dir,
+                "parent" => Variable::Parent,
+                "stem" => Variable::Stem,
+                "ext" => Variable::Ext,
+                "hash" => Variable::Hash,
+                _ => return Err(TemplateError::UnknownVariable(name.to_string())),
+            };
+            let modifier = match modifier {
+                None => None,
+                Some("lower") => Some(Modifier::Lower),
+                Some("upper") => Some(Modifier::Upper),
+                Some(modifier) => return Err(TemplateError::UnknownModifier(modifier.to_string())),
+            };
+
+            segments.push(Segment::Variable(variable, modifier));
+            rest = &rest[end + 1..];
+        }
+
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// Determine if expanding the template requires the content hash.
+    pub fn uses_hash(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::Variable(Variable::Hash, _)))
+    }
+
+    /// Expand the template for the file at the given `/`-separated path relative to the project root.
+    ///
+    /// `hash` is only used if the template contains the `{hash}` variable.
+    pub fn expand(&self, source: &str, hash: &str) -> Result<String, TemplateError> {
+        let path = Path::new(source);
+        let (dir, file_name) = source.rsplit_once('/').unwrap_or(("", source));
+        let stem = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or(file_name);
+        let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
+
+        let mut name = String::new();
+
+        for segment in &self.segments {
+            let (variable, modifier) = match segment {
+                Segment::Literal(literal) => {
+                    name.push_str(literal);
+                    continue;
+                }
+                Segment::Variable(variable, modifier) => (variable, modifier),
+            };
+
+            let value = match variable {
+                Variable::Dir => dir
+                    .split('/')
+                    .filter(|component| !component.is_empty())
+                    .map(|component| format!("{component}__"))
+                    .collect(),
+                Variable::Parent => dir.rsplit('/').next().unwrap_or("").to_string(),
+                Variable::Stem => stem.to_string(),
+                Variable::Ext => {
+                    if ext.is_empty() && name.ends_with('.') {
+                        name.pop();
+                    }
+                    ext.to_string()
+                }
+                Variable::Hash => hash.to_string(),
+            };
+
+            name.push_str(&match modifier {
+                None => value,
+                Some(Modifier::Lower) => value.to_lowercase(),
+                Some(Modifier::Upper) => value.to_uppercase(),
+            });
+        }
+
+        check_name(&name)?;
+        Ok(name)
+    }
+}
+
+/// Check that the output name is a non-empty file name that can be used in LaTeX.
+pub fn check_name(name: &str) -> Result<(), TemplateError> {
+    if name.is_empty() {
+        Err(TemplateError::EmptyName)
+    } else if name.contains(UNSAFE_CHARACTERS) {
+        Err(TemplateError::UnsafeName(name.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(template: &str, source: &str) -> Result<String, TemplateError> {
+        RenameTemplate::parse(template)?.expand(source, "1a2b3c4d")
+    }
+
+    #[test]
+    fn test_expand_default_naming() {
+        assert_eq!(
+            expand("{dir}{stem}.{ext}", "figures/raw/plot.pdf"),
+            Ok("figures__raw__plot.pdf".to_string())
+        );
+        assert_eq!(
+            expand("{dir}{stem}.{ext}", "main.tex"),
+            Ok("main.tex".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expand_hash_and_modifiers() {
+        assert_eq!(
+            expand(
+                "{parent:upper}-{stem:lower}-{hash}.{ext}",
+                "figures/Plot.PDF"
+            ),
+            Ok("FIGURES-plot-1a2b3c4d.PDF".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expand_without_extension() {
+        assert_eq!(
+            expand("{dir}{stem}.{ext}", "content/preamble"),
+            Ok("content__preamble".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expand_unsafe_name() {
+        assert_eq!(
+            expand("{stem} copy.{ext}", "plot.pdf"),
+            Err(TemplateError::UnsafeName("plot copy.pdf".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_check_name() {
+        assert_eq!(check_name("ms.tex"), Ok(()));
+        assert_eq!(
+            check_name("out/ms.tex"),
+            Err(TemplateError::UnsafeName("out/ms.tex".to_string()))
+        );
+        assert_eq!(check_name(""), Err(TemplateError::EmptyName));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            RenameTemplate::parse("{name}"),
+            Err(TemplateError::UnknownVariable("name".to_string()))
+        );
+        assert_eq!(
+            RenameTemplate::parse("{stem:title}"),
+            Err(TemplateError::UnknownModifier("title".to_string()))
+        );
+        assert_eq!(RenameTemplate::parse("{stem"), Err(TemplateError::Unclosed));
+    }
+}
@@ -0,0 +1,176 @@
+use crate::diagnostic::{self, Code, Diagnostic};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DurationError {
+    #[error("Invalid duration {0:?}, expected a number with an optional unit like 90s, 5m or 1h")]
+    Invalid(String),
+}
+
+/// The units of durations, as a factor of milliseconds.
+const UNITS: [(&str, u64); 4] = [("ms", 1), ("s", 1_000), ("m", 60_000), ("h", 3_600_000)];
+
+/// Parse a duration with an optional unit, e.g. `500ms`, `90s`, `5m` or `1h`.
+/// Without unit the number is in seconds.
+pub fn parse_duration(duration: &str) -> Result<Duration, DurationError> {
+    let invalid = || DurationError::Invalid(duration.to_string());
+    let trimmed = duration.trim();
+    let split = trimmed
+        .find(|char: char| !char.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let unit = unit.trim();
+    let factor = if unit.is_empty() {
+        1_000
+    } else {
+        UNITS
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .map(|(_, factor)| *factor)
+            .ok_or_else(invalid)?
+    };
+
+    number
+        .checked_mul(factor)
+        .map(Duration::from_millis)
+        .ok_or_else(invalid)
+}
+
+/// The time limit of the run, set with `--timeout`.
+#[derive(Debug)]
+struct Deadline {
+    timeout: Duration,
+    expires: Instant,
+    /// The partial output, which is removed when the run is aborted.
+    cleanup: Mutex<Vec<Cleanup>>,
+}
+
+/// How partial output is removed.
+#[derive(Debug)]
+enum Cleanup {
+    /// Remove the file or directory.
+    Remove(PathBuf),
+    /// Remove the content of the directory, which already existed.
+    Empty(PathBuf),
+}
+
+static DEADLINE: OnceLock<Deadline> = OnceLock::new();
+
+/// The temporary files and directories, which are removed when the run exits early.
+static TEMPORARY: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Start the time limit of the run.
+pub fn start(timeout: Duration) {
+    DEADLINE
+        .set(Deadline {
+            timeout,
+            expires: Instant::now() + timeout,
+            cleanup: Mutex::default(),
+        })
+        .expect("The timeout is only started once");
+}
+
+/// Remove the file or directory if the run is aborted, as it only contains partial output.
+pub fn remove_on_expiry(path: PathBuf) {
+    on_expiry(Cleanup::Remove(path));
+}
+
+/// Remove the content of the directory if the run is aborted, as it only contains partial output.
+pub fn empty_on_expiry(dir: PathBuf) {
+    on_expiry(Cleanup::Empty(dir));
+}
+
+fn on_expiry(cleanup: Cleanup) {
+    if let Some(deadline) = DEADLINE.get() {
+        deadline.cleanup.lock().unwrap().push(cleanup);
+    }
+}
+
+/// Remove the temporary file or directory if the run exits early with [`exit`],
+/// as its guard is not dropped then.
+pub fn remove_on_exit(path: PathBuf) {
+    TEMPORARY.lock().unwrap().push(path);
+}
+
+/// Exit the process with the given code, removing the temporary files and directories first.
+pub fn exit(code: i32) -> ! {
+    for path in TEMPORARY.lock().unwrap().iter() {
+        remove(path);
+    }
+    process::exit(code);
+}
+
+/// Remove the file or directory, ignoring errors as missing output is already clean.
+fn remove(path: &Path) {
+    let _ = if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+}
+
+/// Abort the run if the time limit is exceeded, removing the partial output.
+///
+/// This is called between files, so no file is left half-written.
+pub fn check() {
+    let Some(deadline) = DEADLINE.get() else {
+        return;
+    };
+    if Instant::now() < deadline.expires {
+        return;
+    }
+
+    for cleanup in deadline.cleanup.lock().unwrap().iter() {
+        match cleanup {
+            Cleanup::Remove(path) => remove(path),
+            Cleanup::Empty(dir) => {
+                for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+                    remove(&entry.path());
+                }
+            }
+        }
+    }
+
+    diagnostic::print(
+        &Diagnostic::error(
+            Code::Timeout,
+            format!(
+                "Aborted because the run exceeded the timeout of {:?}, \
+                 the partial output was removed",
+                deadline.timeout
+            ),
+        ),
+        false,
+    );
+    exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("5 m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert_eq!(
+            parse_duration("1.5h"),
+            Err(DurationError::Invalid("1.5h".to_string()))
+        );
+        assert_eq!(
+            parse_duration("5d"),
+            Err(DurationError::Invalid("5d".to_string()))
+        );
+    }
+}
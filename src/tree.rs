@@ -0,0 +1,154 @@
+use std::fmt::Write;
+
+/// The maximum number of files shown, to keep the output of large projects readable.
+pub const MAX_FILES: usize = 500;
+
+const ARROW_COLOR: &str = "\x1b[2m";
+const NAME_COLOR: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// Render the source files as a tree, with an arrow from each file to its flattened name.
+///
+/// The files are `(source, name)` pairs with `/`-separated source paths, sorted by source.
+/// Only the first `limit` files are shown, followed by the number of omitted files.
+pub fn render_tree(root: &str, files: &[(String, String)], color: bool, limit: usize) -> String {
+    // The tree lines with the flattened name of the files
+    let mut lines: Vec<(String, Option<&str>)> = vec![(format!("{root}/"), None)];
+    // The directories of the previous file
+    let mut open: Vec<&str> = Vec::new();
+
+    let shown = &files[..files.len().min(limit)];
+    for (index, (source, name)) in shown.iter().enumerate() {
+        let components: Vec<_> = source.split('/').collect();
+        let (file, directories) = components.split_last().unwrap();
+
+        let common = open
+            .iter()
+            .zip(directories)
+            .take_while(|(a, b)| a == b)
+            .count();
+        open.truncate(common);
+
+        for depth in common..directories.len() {
+            let last = is_last(&files[index..], &components[..=depth]);
+            lines.push((
+                format!(
+                    "{}{}/",
+                    prefix(&files[index..], &components[..depth], last),
+                    directories[depth]
+                ),
+                None,
+            ));
+            open.push(directories[depth]);
+        }
+
+        let last = is_last(&files[index..], &components);
+        lines.push((
+            format!("{}{file}", prefix(&files[index..], directories, last)),
+            Some(name.as_str()),
+        ));
+    }
+
+    let width = lines
+        .iter()
+        .map(|(line, _)| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut tree = String::new();
+    for (line, name) in lines {
+        match name {
+            Some(name) => {
+                let arrow = format!(" {}→ ", "─".repeat(width - line.chars().count() + 1));
+                if color {
+                    writeln!(
+                        tree,
+                        "{line}{ARROW_COLOR}{arrow}{RESET}{NAME_COLOR}{name}{RESET}"
+                    )
+                } else {
+                    writeln!(tree, "{line}{arrow}{name}")
+                }
+            }
+            None => writeln!(tree, "{line}"),
+        }
+        .unwrap();
+    }
+
+    if files.len() > shown.len() {
+        writeln!(tree, "... and {} more file(s)", files.len() - shown.len()).unwrap();
+    }
+    tree
+}
+
+/// Determine if the entry with the given path components is the last one in its directory,
+/// given the remaining files starting with the one containing the entry.
+fn is_last(remaining: &[(String, String)], components: &[&str]) -> bool {
+    let parent = &components[..components.len() - 1];
+    let entry = components[components.len() - 1];
+
+    !remaining.iter().any(|(source, _)| {
+        let other: Vec<_> = source.split('/').collect();
+        other.len() > parent.len()
+            && other[..parent.len()] == *parent
+            && other[parent.len()] != entry
+    })
+}
+
+/// The box drawing characters in front of an entry in the given directory.
+fn prefix(remaining: &[(String, String)], directories: &[&str], last: bool) -> String {
+    let mut prefix = String::new();
+
+    for depth in 1..=directories.len() {
+        if is_last(remaining, &directories[..depth]) {
+            prefix.push_str("    ");
+        } else {
+            prefix.push_str("│   ");
+        }
+    }
+
+    prefix.push_str(if last { "└── " } else { "├── " });
+    prefix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(sources: &[&str]) -> Vec<(String, String)> {
+        sources
+            .iter()
+            .map(|source| (source.to_string(), source.replace('/', "__")))
+            .collect()
+    }
+
+    #[test]
+    fn test_render_tree() {
+        let files = files(&[
+            "chapters/intro.tex",
+            "chapters/parts/a.tex",
+            "figures/plot.pdf",
+            "main.tex",
+        ]);
+
+        assert_eq!(
+            render_tree("project", &files, false, MAX_FILES),
+            "project/\n\
+             ├── chapters/\n\
+             │   ├── intro.tex ─→ chapters__intro.tex\n\
+             │   └── parts/\n\
+             │       └── a.tex ─→ chapters__parts__a.tex\n\
+             ├── figures/\n\
+             │   └── plot.pdf ──→ figures__plot.pdf\n\
+             └── main.tex ──────→ main.tex\n"
+        );
+    }
+
+    #[test]
+    fn test_render_tree_truncated() {
+        let files = files(&["a.tex", "b.tex", "c.tex"]);
+
+        assert_eq!(
+            render_tree("project", &files, false, 1),
+            "project/\n├── a.tex ─→ a.tex\n... and 2 more file(s)\n"
+        );
+    }
+}
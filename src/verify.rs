@@ -0,0 +1,95 @@
+use crate::{
+    diagnostic::{Code, Diagnostic},
+    is_tex,
+    mapping::Mapping,
+    rewrite::{join_continued_commands, trace_imports},
+    scanner::{LineKind, LineScanner},
+    scope::Scope,
+    Args,
+};
+use std::{fs, io, path::Path};
+
+/// Check that the flattened project in the output directory is consistent.
+///
+/// Every reference of the rewritten files must point to a file of the output,
+/// and no reference may still contain a path separator.
+/// Returns an error for each violation, sorted by file.
+pub fn verify_round_trip(out: &Path, args: &Args) -> io::Result<Vec<Diagnostic>> {
+    let mut names: Vec<_> = fs::read_dir(out)?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<io::Result<_>>()?;
+    names.sort();
+
+    let mut mapping = if args.normalize_unicode {
+        Mapping::with_unicode_normalization()
+    } else {
+        Mapping::default()
+    };
+    for name in &names {
+        mapping.insert(name.clone(), name.clone());
+    }
+
+    let mut violations = Vec::new();
+    for name in names.iter().filter(|name| is_tex(Path::new(name), args)) {
+        let Ok(content) = fs::read_to_string(out.join(name)) else {
+            continue;
+        };
+
+        let mut scanner = LineScanner::new(Path::new(name));
+        let mut scope = Scope::default();
+        for (index, line, _) in join_continued_commands(&content, args) {
+            if scanner.scan(line) != LineKind::Latex {
+                continue;
+            }
+
+            scope.define(line, name, &mapping);
+            let violation =
+                |message| Diagnostic::error(Code::RoundTrip, message).at(name, index + 1);
+            trace_imports(line, args, &mapping, &scope, |import| {
+                if import.original.contains('/') {
+                    violations.push(violation(format!(
+                        "\\{}{{{}}} still contains a path separator",
+                        import.command, import.original
+                    )));
+                }
+                for reference in import.unresolved {
+                    violations.push(violation(format!(
+                        "\\{}{{{reference}}} doesn't resolve to an output file",
+                        import.command
+                    )));
+                }
+            });
+        }
+    }
+
+    Ok(violations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("main.tex"),
+            "\\input{intro}\n\\input{chapters/outro}\n\\includegraphics{plot}\n\\usepackage{amsmath}",
+        )
+        .unwrap();
+        fs::write(dir.path().join("intro.tex"), "").unwrap();
+
+        assert_eq!(
+            verify_round_trip(dir.path(), &Args::with_flags(&[]))
+                .unwrap()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                r"Error [LF0026]: main.tex:2: \input{chapters/outro} still contains a path separator",
+                r"Error [LF0026]: main.tex:2: \input{chapters/outro} doesn't resolve to an output file",
+                r"Error [LF0026]: main.tex:3: \includegraphics{plot} doesn't resolve to an output file",
+            ]
+        );
+    }
+}